@@ -1,25 +1,117 @@
-use std::sync::mpsc;
+use std::sync::{Arc, mpsc};
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+
+use crate::clock::TimeBudget;
+use crate::game_manager::GameResult;
+use crate::rules::IllegalMovePolicy;
 
 use super::*;
 
-pub trait Actor {
+/// Everything an actor can do on its turn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move(CellPos),
+    Resign,
+    /// take back the opponent's last move and our own before it
+    RequestUndo,
+    /// answer to the opponent's undo request, sent while it waits for one
+    AnswerUndo(bool),
+    /// actor can't continue (e.g. lost connection)
+    Forfeit(String),
+    /// stopped waiting at the end of its time budget, the clock decides whether that lost the game
+    TimedOut,
+}
+
+pub trait Actor: Send {
     fn next(&mut self, board: &Board) -> CellPos;
+    /// Actors that do more than just moving override this instead of `next`.
+    fn act(&mut self, board: &Board) -> Action {
+        Action::Move(self.next(board))
+    }
+    /// Score of the last move returned by `next` from the mover's point of view, if the actor searches.
+    fn last_score(&self) -> Option<i32> {
+        None
+    }
+    /// Told before each move when the game is played with a clock.
+    fn set_time_budget(&mut self, _budget: TimeBudget) {}
+    /// Told once before the first move, engines search with the game's idea of a win.
+    fn set_rules(&mut self, _rules: Rules) {}
+    /// Told once before the first move, set when the game is stopped from the outside.
+    /// Searches wrap up quickly once it is, their move won't be played anyway.
+    fn set_stop(&mut self, _stop: Arc<AtomicBool>) {}
+    /// Asked before each of the actor's moves.
+    fn resign(&mut self, _board: &Board) -> bool {
+        false
+    }
+    /// Asked before each of the actor's moves, the opponent then gets to `accept_draw`.
+    fn offer_draw(&mut self, _board: &Board) -> bool {
+        false
+    }
+    fn accept_draw(&mut self, _board: &Board) -> bool {
+        false
+    }
+    /// Asked when the opponent requests an undo.
+    fn accept_undo(&mut self, _board: &Board) -> bool {
+        false
+    }
+    /// Tells the actor whether its undo request went through.
+    fn undo_answered(&mut self, _board: &Board, _accepted: bool) {}
+    /// Called for both actors once the game ends, `board` includes the final move.
+    fn game_over(&mut self, _board: &Board, _result: &GameResult) {}
+    /// What `GameManager` does about the actor's illegal moves, engines have no excuse for them.
+    fn illegal_move_policy(&self) -> IllegalMovePolicy {
+        IllegalMovePolicy::Forfeit(1)
+    }
 }
 
 pub struct Player {
-    thread_receiver: mpsc::Receiver<CellPos>
+    thread_receiver: mpsc::Receiver<Action>,
+    /// when the clock of the current move runs out, if there is one
+    deadline: Option<Instant>,
 }
 
 impl Player {
-    pub fn new(thread_receiver: mpsc::Receiver<CellPos>) -> Self {
+    pub fn new(thread_receiver: mpsc::Receiver<Action>) -> Self {
         Self {
-            thread_receiver
+            thread_receiver,
+            deadline: None,
         }
     }
 }
 
 impl Actor for Player {
-    fn next(&mut self, _: &Board) -> CellPos {
-        self.thread_receiver.recv().unwrap()
+    fn next(&mut self, board: &Board) -> CellPos {
+        loop {
+            match self.act(board) {
+                Action::Move(cp) => return cp,
+                Action::Forfeit(reason) => panic!("no move to return, {}", reason),
+                _ => {}
+            }
+        }
+    }
+    fn act(&mut self, _: &Board) -> Action {
+        let received = match self.deadline {
+            Some(deadline) => self.thread_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self.thread_receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(action) => action,
+            Err(mpsc::RecvTimeoutError::Timeout) => Action::TimedOut,
+            // the window dropped its end, e.g. to start a new game
+            Err(mpsc::RecvTimeoutError::Disconnected) => Action::Forfeit("player left".to_string()),
+        }
+    }
+    fn set_time_budget(&mut self, budget: TimeBudget) {
+        self.deadline = Some(Instant::now() + budget.remaining);
+    }
+    /// Waits for the human's `Action::AnswerUndo`, anything else declines.
+    /// Engines never ask, so it's always another human waiting for the answer.
+    fn accept_undo(&mut self, _: &Board) -> bool {
+        matches!(self.thread_receiver.recv(), Ok(Action::AnswerUndo(true)))
+    }
+    /// a misclick shouldn't lose the game
+    fn illegal_move_policy(&self) -> IllegalMovePolicy {
+        IllegalMovePolicy::Retry
     }
 }
\ No newline at end of file
@@ -2,139 +2,258 @@
 mod bob;
 mod noob;
 mod john;
+mod nnue;
+mod verify;
+mod ordering;
 
 pub use bob::BobAI;
 pub use noob::NoobAI;
-pub use john::{John, BoardState};
+pub use john::{John, BoardState, Reason};
+pub use nnue::{Network, Accumulator};
 
 use std::ops::Neg;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use rand::seq::SliceRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
+
+use crate::patterns::{self, ShapeKind};
 
 use super::*;
 
 
-const LOST: i32 = -1_000_000;
-const WIN: i32 = 1_000_000;
+pub(crate) const LOST: i32 = -1_000_000;
+pub(crate) const WIN: i32 = 1_000_000;
+/// Scores at least this far out are forced results, `WIN - n` makes five `n` plies after the next move.
+/// They're relative to the position they belong to, so they can be stored in memory as they are.
+pub(crate) const MATE_BOUND: i32 = WIN - 1_000;
 
-pub struct RandomAI {}
+pub(crate) fn is_win(score: i32) -> bool {
+    score >= MATE_BOUND
+}
 
-impl Actor for RandomAI {
-    fn next(&mut self, board: &Board) -> CellPos {
-        random()
-    }
+pub(crate) fn is_loss(score: i32) -> bool {
+    score <= -MATE_BOUND
 }
 
-fn valid_move(board: &Board, cp: CellPos) -> bool {
-    if board[cp].is_some() {
-        return false;
+/// A child position's score seen from its parent, a forced result gets one ply further away.
+pub(crate) fn from_child(score: i32) -> i32 {
+    match score {
+        score if is_win(score) => -(score - 1),
+        score if is_loss(score) => -(score + 1),
+        score => -score
     }
+}
 
-    let mut has_d_neigbor = false;
-
-    for i in 0..8 {
-        if let Some(next) = cp.try_add(dir(i)) {
-            has_d_neigbor |= board[next].is_some();
-            if let Some(next) = next.try_add(dir(i)) {
-                has_d_neigbor |= board[next].is_some();
-            }
-        }
+/// The parent's window bound `score` as seen from a child, undoes `from_child`.
+pub(crate) fn to_child(score: i32) -> i32 {
+    match score {
+        score if is_loss(score) => 1 - score,
+        score if is_win(score) => -score - 1,
+        score => -score
     }
+}
 
-    has_d_neigbor
+/// How often the narrowed windows of the searches had to be searched again.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchStats {
+    /// null window searches of later moves that turned out better than the first one
+    pub researches: usize,
+    /// aspiration windows the root score fell below or rose above
+    pub fail_lows: usize,
+    pub fail_highs: usize,
 }
 
-pub fn bobs_shallow_eval(board: &Board, debug: bool) -> i32 {
-    let mut counter_f = vec![[0; 3]; 6];
-    let mut counter_e = vec![[0; 3]; 6];
+impl std::ops::AddAssign for SearchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.researches += other.researches;
+        self.fail_lows += other.fail_lows;
+        self.fail_highs += other.fail_highs;
+    }
+}
 
-    // bob is still a noob he will not consider 6s bad 
+/// Half width of the first aspiration window, it grows fourfold with every failure.
+const ASPIRATION: i32 = 50;
+
+/// Searches the root with a window around `guess`, widening it on the side the score fell out of
+/// until the score lands inside. `search` gets the window and returns the score first.
+/// `widen` is asked after every failure, once it says no the failed result is returned as it is.
+pub(crate) fn aspiration<T>(guess: Option<i32>, stats: &mut SearchStats, mut search: impl FnMut(i32, i32) -> (i32, T),
+                            widen: impl Fn() -> bool) -> (i32, T) {
+    // a forced result is exact anyway, and a window around it wouldn't make sense
+    let Some(guess) = guess.filter(|guess| !is_win(*guess) && !is_loss(*guess)) else {
+        return search(LOST-1, WIN+1);
+    };
+    let (mut low, mut high) = (ASPIRATION, ASPIRATION);
+    let window = |width: i32, side: i32| match width {
+        width if width > 10_000 => side * (WIN+1),
+        width => guess + side * width
+    };
+    loop {
+        let (alpha, beta) = (window(low, -1), window(high, 1));
+        let result = search(alpha, beta);
+        if result.0 <= alpha && alpha > LOST-1 {
+            stats.fail_lows += 1;
+            low *= 4;
+        }
+        else if result.0 >= beta && beta < WIN+1 {
+            stats.fail_highs += 1;
+            high *= 4;
+        }
+        else {
+            return result;
+        }
+        if !widen() {
+            return result;
+        }
+    }
+}
 
+/// "win in 3", "loss in 2", counted in moves of the winner, or just the score.
+pub(crate) fn describe_score(score: i32) -> String {
+    match score {
+        score if is_win(score) => format!("win in {}", (WIN - score) / 2 + 1),
+        score if is_loss(score) => format!("loss in {}", ((score - LOST + 1) / 2).max(1)),
+        score => score.to_string()
+    }
+}
 
-    for x in 0..15 {
-        for y in 0..15 {
-            let cp = cell(x, y);
+pub struct RandomAI {}
 
-            let dir_count = board.compute_dir_lengths_from(cp);
+impl Actor for RandomAI {
+    fn next(&mut self, board: &Board) -> CellPos {
+        // never asked on a full board
+        board.free_positions().choose(&mut rand::thread_rng()).unwrap()
+    }
+    fn accept_undo(&mut self, _: &Board) -> bool {
+        true
+    }
+}
 
-            if board[cp].is_none() {
-                for i in 0..4 {
-                    let Some(next1) = cp.try_add(dir(i)) else {
-                        continue;
-                    };
-                    let Some(next2) = cp.try_add(dir(i+4)) else {
-                        continue;
-                    };
-                    if board[next1].is_none() || board[next1] != board[next2] {
-                        continue;
-                    }
+pub const ENGINES: [&str; 4] = ["john", "bob", "noob", "random"];
+
+/// A built-in engine and its strength, each engine only reads its own knobs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    /// `john`, `bob`, `noob` or `random`
+    pub name: String,
+    /// John: positions worth of compute per move
+    pub compute: f32,
+    /// John: how much more compute the better half of moves gets each iteration
+    pub mp: f32,
+    /// John: how many times moves are re-ordered by their results
+    pub count_iter: i32,
+    /// Bob and Noob: search depth
+    pub depth: usize,
+    /// John: network file to evaluate leaves with, see `Network`
+    pub network: Option<String>,
+}
 
-                    let count = dir_count[i]+dir_count[i+4];
+impl EngineConfig {
+    /// The engine with its usual settings.
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), compute: 1_000_000., mp: 2., count_iter: 4, depth: 3, network: None }
+    }
 
-                    let mut bounded = 2;
+    pub fn build(&self, debug: bool) -> Box<dyn Actor> {
+        match self.name.as_str() {
+            "john" => {
+                let mut john = John::new(self.compute, self.mp, self.count_iter);
+                john.debug = debug;
+                // the file was read once already when the config was parsed
+                john.network = self.network.as_ref().map(|path| {
+                    Arc::new(Network::load(path).unwrap_or_else(|e| panic!("can't load {}: {}", path, e)))
+                });
+                Box::new(john)
+            }
+            "bob" => {
+                let mut bob = BobAI::new(self.depth as u8);
+                bob.debug = debug;
+                Box::new(bob)
+            }
+            "noob" => Box::new(NoobAI { depth: self.depth, rules: Rules::default() }),
+            _ => Box::new(RandomAI {}),
+        }
+    }
+}
 
-                    if let Some(next) = cp.try_add((dir_count[i]+1) * dir(i)) {
-                        if board[next].is_none() {
-                            bounded -= 1;
-                        } 
-                    }
-                    if let Some(next) = cp.try_add((dir_count[i+4]+1)*dir(i+4)) {
-                        if board[next].is_none() {
-                            bounded -= 1;
-                        } 
-                    } 
-
-                    if count >= 5 {
-                        continue;
-                    }
+/// `john`, `bob:depth=4`, `john:compute=2e6,mp=2.5,count_iter=3`, `john:network=nets/15x15.gnn`
+impl FromStr for EngineConfig {
+    type Err = String;
 
-                    if board[next1].unwrap() == board.turn {
-                        counter_f[count][bounded] += count;
-                    }
-                    else {
-                        if bounded == 0 && count == 4 {
-                            counter_e[count][1] += count;
-                        }
-                        else {
-                            counter_e[count][bounded] += count;
-                        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        if !ENGINES.contains(&name) {
+            return Err(format!("unknown engine {}", name));
+        }
+        let mut config = Self::new(name);
+        for param in params.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').ok_or(format!("{} needs a value", param))?;
+            let invalid = || format!("invalid value {} for {}", value, key);
+            match key {
+                "compute" => config.compute = value.parse().map_err(|_| invalid())?,
+                "mp" => config.mp = value.parse().map_err(|_| invalid())?,
+                "count_iter" => config.count_iter = value.parse().map_err(|_| invalid())?,
+                "depth" => {
+                    config.depth = value.parse().map_err(|_| invalid())?;
+                    // a search of depth 0 has no move to return, and Bob keeps its depth in a u8
+                    if !(1..=u8::MAX as usize).contains(&config.depth) {
+                        return Err(invalid());
                     }
                 }
-                continue;
+                "network" => {
+                    Network::load(value).map_err(|e| format!("can't load network {}: {}", value, e))?;
+                    config.network = Some(value.to_string());
+                }
+                _ => return Err(format!("unknown parameter {}", key)),
             }
+        }
+        Ok(config)
+    }
+}
 
-            for i in 0..4 {
-                let count = dir_count[i]+dir_count[i+4]-1;
-                let mut bounded = 2;
-                if let Some(next) = cp.try_add((dir_count[i])*dir(i)) {
-                    if board[next].is_none() {
-                        bounded -= 1;
-                    } 
-                }
-                if let Some(next) = cp.try_add((dir_count[i+4])*dir(i+4)) {
-                    if board[next].is_none() {
-                        bounded -= 1;
-                    } 
-                }
-                if bounded == 2 {
-                    continue;
-                }
+/// Built-in engine by name with its usual settings.
+pub fn engine(name: &str, debug: bool) -> Option<Box<dyn Actor>> {
+    ENGINES.contains(&name).then(|| EngineConfig::new(name).build(debug))
+}
 
-                if count >= 6 {
-                    continue;
-                }
-                assert_ne!(count, 0);
+pub fn bobs_shallow_eval(board: &Board, debug: bool) -> i32 {
+    let (counter_f, counter_e) = count_lines(board);
+    bobs_score(&counter_f, &counter_e, debug)
+}
 
-                if board[cp].unwrap() == board.turn {
-                    counter_f[count][bounded] += 1;
-                }
-                else {
-                    counter_e[count][bounded] += 1;
-                }
-            }
+/// `bobs_shallow_eval`, and whether the side to move has a three or four to push
+/// or the opponent a four or open three to answer.
+pub(crate) fn bobs_eval_with_threats(board: &Board) -> (i32, bool) {
+    let (counter_f, counter_e) = count_lines(board);
+    let threats = (3..6).any(|count| counter_f[count][0] + counter_f[count][1] != 0)
+        || (4..6).any(|count| counter_e[count][0] + counter_e[count][1] != 0)
+        || counter_e[3][0] != 0;
+    (bobs_score(&counter_f, &counter_e, false), threats)
+}
+
+/// Shapes of the side to move and of the opponent by stones and open ends: open ones (two ways to grow)
+/// in column 0, the ones with a single way in column 1. Each shape counts as many times as it has stones.
+fn count_lines(board: &Board) -> (Vec<[usize; 2]>, Vec<[usize; 2]>) {
+    let count = |stone: Stone| {
+        let mut counter = vec![[0; 2]; 6];
+        for shape in patterns::shapes(board, stone) {
+            let (stones, bounded) = match shape.kind {
+                ShapeKind::Five => (5, 0),
+                ShapeKind::OpenFour => (4, 0),
+                ShapeKind::SimpleFour | ShapeKind::BrokenFour => (4, 1),
+                ShapeKind::OpenThree | ShapeKind::BrokenThree => (3, 0),
+                ShapeKind::SplitThree | ShapeKind::ClosedThree => (3, 1),
+                ShapeKind::OpenTwo => (2, 0),
+            };
+            counter[stones][bounded] += stones;
         }
-    }
+        counter
+    };
+    (count(board.turn), count(-board.turn))
+}
 
+fn bobs_score(counter_f: &[[usize; 2]], counter_e: &[[usize; 2]], debug: bool) -> i32 {
     if debug   {
         println!("{:?}", counter_f);
         println!("{:?}", counter_e);
@@ -147,13 +266,13 @@ pub fn bobs_shallow_eval(board: &Board, debug: bool) -> i32 {
     if counter_e[5][0]+counter_e[5][1] != 0 {
         return LOST;
     }
-    if counter_f[4][0]+counter_f[4][1]+counter_f[4][2] != 0 {
+    if counter_f[4][0]+counter_f[4][1] != 0 {
         return WIN;
     }
     if counter_e[4][0] != 0 {
         return LOST;
     }
-    if counter_f[3][0] != 0 && counter_e[4][1]+counter_e[4][2] == 0 {
+    if counter_f[3][0] != 0 && counter_e[4][1] == 0 {
         return WIN;
     }
     if (counter_e[4][1]/4 > 1) {
@@ -178,7 +297,6 @@ pub fn bobs_shallow_eval(board: &Board, debug: bool) -> i32 {
         counter_e[2][0] * 5,
         counter_e[2][1] * 1,
         counter_e[4][1] * 70,
-        counter_e[4][2] * 70,
         counter_e[3][0] * 70,
         counter_e[3][1] * 10
     ];
@@ -191,4 +309,68 @@ pub fn bobs_shallow_eval(board: &Board, debug: bool) -> i32 {
     }
 
     result
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mate_distance() {
+        assert_eq!(describe_score(WIN), "win in 1");
+        // we play the open four, they block one end, we make five
+        assert_eq!(describe_score(from_child(from_child(WIN))), "win in 2");
+        assert_eq!(describe_score(from_child(WIN)), "loss in 1");
+        // losing later is better
+        assert!(from_child(from_child(from_child(WIN))) > from_child(WIN));
+        assert_eq!(from_child(120), -120);
+        for score in [WIN, WIN - 4, LOST + 3, 120, -7] {
+            assert_eq!(from_child(to_child(score)), score);
+        }
+    }
+
+    #[test]
+    fn test_engine_config() {
+        assert_eq!("john".parse(), Ok(EngineConfig::new("john")));
+        let john = "john:compute=2e6,mp=2.5,count_iter=3".parse::<EngineConfig>().unwrap();
+        assert_eq!((john.compute, john.mp, john.count_iter), (2e6, 2.5, 3));
+        assert_eq!("bob:depth=4".parse::<EngineConfig>().unwrap().depth, 4);
+
+        assert!("fred".parse::<EngineConfig>().is_err());
+        assert!("bob:depth".parse::<EngineConfig>().is_err());
+        assert!("bob:depth=deep".parse::<EngineConfig>().is_err());
+        assert!("bob:depth=0".parse::<EngineConfig>().is_err());
+        assert!("bob:depth=300".parse::<EngineConfig>().is_err());
+        assert!("bob:width=3".parse::<EngineConfig>().is_err());
+        assert!("john:network=no-such-file.gnn".parse::<EngineConfig>().is_err());
+    }
+
+    #[test]
+    fn test_random_plays_free_cells() {
+        let mut board = Board::new();
+        while board.free_positions().count() > 1 {
+            board.make_move(board.free_positions().next().unwrap());
+        }
+        let last = board.free_positions().next().unwrap();
+        assert_eq!(RandomAI {}.next(&board), last);
+    }
+
+    #[test]
+    fn test_freestyle_search() {
+        // joining the two runs makes six, only a win under freestyle rules, both ends are blocked
+        let mut board = Board::new();
+        for (x, black) in [2, 3, 4, 6, 7].into_iter().zip([cell(1, 7), cell(8, 7), cell(0, 0), cell(14, 14), cell(0, 14)]) {
+            board.make_move(cell(x, 7));
+            board.make_move(black);
+        }
+        let mut bob = BobAI::new(1);
+        bob.debug = false;
+        bob.set_rules(Rules::Freestyle);
+        assert_eq!(bob.next(&board), cell(5, 7));
+        assert!(bob.last_score().is_some_and(is_win));
+
+        let mut bob = BobAI::new(1);
+        bob.debug = false;
+        bob.next(&board);
+        assert!(!bob.last_score().is_some_and(is_win));
+    }
+}
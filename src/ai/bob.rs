@@ -1,57 +1,81 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::clock::TimeBudget;
+use crate::patterns;
 
 use super::*;
+use super::ordering::MoveOrdering;
+
+/// Plies of forcing moves `quiesce` follows past the last full ply.
+const QUIESCENCE_DEPTH: u8 = 4;
 
+/// (remaining depth, hash), remaining rather than current depth so that entries survive changing `depth`
 #[derive(Eq, PartialEq, Hash)]
 struct MemoryEntry(u8, u64);
 pub struct BobAI {
     pub depth: u8,
     memory: HashMap<MemoryEntry, (i32, Option<CellPos>)>,
     pub used_memory: usize,
-    pub computed_positions: usize
+    pub computed_positions: usize,
+    /// positions `quiesce` went through beyond the leaves
+    pub quiescence_positions: usize,
+    last_score: Option<i32>,
+    ordering: MoveOrdering,
+    /// order moves with `ordering` rather than by evaluating every child
+    pub move_ordering: bool,
+    /// null windows for all but the first move and aspiration windows at the root
+    pub narrow_windows: bool,
+    pub stats: SearchStats,
+    time_budget: Option<TimeBudget>,
+    /// what counts as a win in the searched lines
+    rules: Rules,
+    /// the game was stopped, search no deeper than the root's children
+    stop: Arc<AtomicBool>,
+    pub debug: bool
 }
 
 impl BobAI {
     pub fn new(depth: u8) -> Self {
-        BobAI { depth, memory: HashMap::default(), used_memory: 0, computed_positions: 0 }
+        BobAI { depth, memory: HashMap::default(), used_memory: 0, computed_positions: 0, quiescence_positions: 0, last_score: None, ordering: MoveOrdering::new(), move_ordering: true, narrow_windows: true, stats: SearchStats::default(), time_budget: None, rules: Rules::default(), stop: Default::default(), debug: true }
     }
     
    
     pub fn minmax(&mut self, cur_depth: u8, board: &mut Board, mut alpha: i32, beta: i32) -> (i32, Option<CellPos>) {
         // things we can assume here: 
             // 1. we haven't won already
-        if let Some(result) = self.memory.get(&MemoryEntry(cur_depth, board.hash)) {
+        if let Some(result) = self.memory.get(&MemoryEntry(self.depth - cur_depth, board.hash)) {
             self.used_memory += 1;
             return *result;
         }
         self.computed_positions += 1;
-        if cur_depth == self.depth {
-            let result = (bobs_shallow_eval(board, false), None);
-            self.memory.insert(MemoryEntry(cur_depth, board.hash), result);
+        // the root still needs a move to return
+        if cur_depth == self.depth || (cur_depth > 0 && self.stop.load(Ordering::Relaxed)) {
+            let result = (self.quiesce(board, alpha, beta, QUIESCENCE_DEPTH), None);
+            // a score outside of the window is only a bound
+            if result.0 > alpha && result.0 < beta {
+                self.memory.insert(MemoryEntry(0, board.hash), result);
+            }
             return result;
         }
         let mut result = (LOST-1, None);
-        let mut moves = vec![];
-        for x in 0..15 {
-            for y in 0..15 {
-                let cp = cell(x, y);
+        let mut moves = board.candidates().collect::<Vec<_>>();
 
-                if !valid_move(board, cp) {
-                    continue;
-                }
-
-                moves.push(cp);
-            }
+        if self.move_ordering {
+            self.ordering.order(board, &mut moves);
+        } else {
+            let mut evals = moves.into_iter().map(|cp| {
+                board.make_move(cp);
+                let result = (bobs_shallow_eval(board, false), cp);
+                board.unmake_move(cp);
+                result
+            }).collect::<Vec<_>>();
+            evals.sort_unstable_by_key(|x| x.0);
+            moves = evals.into_iter().map(|(_, cp)| cp).collect();
         }
 
-        let mut moves = moves.into_iter().map(|cp| {
-            board.make_move(cp);
-            let result = (bobs_shallow_eval(board, false), cp);
-            board.unmake_move(cp);
-            result
-        }).collect::<Vec<_>>();
-        moves.sort_unstable_by_key(|x| x.0);
-
         // for new_depth in cur_depth+2..self.depth {
         //     let mut local_alpha = alpha;
         //     moves = moves.into_iter().map(|(_leval, cp)| {
@@ -66,16 +90,24 @@ impl BobAI {
         //     }).collect::<Vec<_>>();
         //     moves.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
         // }
-        for (_, cp) in moves {
+        for (i, cp) in moves.into_iter().enumerate() {
             assert!(board.make_move(cp));
 
-            if board.check_win_from(cp) {
+            if self.rules.is_win(board, cp) {
                 board.unmake_move(cp);
                 return (WIN, Some(cp));
             }
 
-            let mut eval = self.minmax(cur_depth+1, board, -beta, -alpha);
-            eval.0 = -eval.0;
+            // the first move is likely the best, the others only have to be shown worse than it
+            let null_window = i > 0 && self.narrow_windows;
+            let (upper, lower) = if null_window { (alpha+1, alpha) } else { (beta, alpha) };
+            let mut eval = self.minmax(cur_depth+1, board, to_child(upper), to_child(lower));
+            eval.0 = from_child(eval.0);
+            if null_window && eval.0 > alpha && eval.0 < beta {
+                self.stats.researches += 1;
+                eval = self.minmax(cur_depth+1, board, to_child(beta), to_child(alpha));
+                eval.0 = from_child(eval.0);
+            }
 
             board.unmake_move(cp);
 
@@ -84,30 +116,160 @@ impl BobAI {
                 if eval.0 > alpha {
                     alpha = eval.0;
                     if alpha >= beta {
+                        self.ordering.cutoff(board, cp, (self.depth - cur_depth) as i32);
                         return result;
                     }
                 }
             }
         }
         // self.memory.insert(MemoryEntry(cur_depth, board.hash), result);
+        if let Some(cp) = result.1 {
+            self.ordering.best(board, cp);
+        }
+        result
+
+    }
+
+    /// Score of the position once the fours and open threes pending in it are played out.
+    /// Only forcing moves are searched: our fours, and when we're under threat the moves that answer it.
+    fn quiesce(&mut self, board: &mut Board, mut alpha: i32, beta: i32, depth_left: u8) -> i32 {
+        let (stand_pat, threats) = bobs_eval_with_threats(board);
+        // working out the threats is far more expensive than the eval, only do it when there are some
+        if !threats || depth_left == 0 {
+            return stand_pat;
+        }
+        let (mut moves, threatened) = match BoardState::compute(board) {
+            BoardState::OneMoveWin(_) => return WIN,
+            BoardState::OneMoveLoss(_) => return LOST+1,
+            BoardState::DefendOrCounterFour(mvs) if mvs.is_empty() => return LOST+3,
+            BoardState::ForcedDefense(cp) => (vec![cp], true),
+            BoardState::DefendOrCounterFour(mvs) => (mvs, true),
+            BoardState::TwoMoveWin(cp) | BoardState::DoubleFour(cp) | BoardState::FourThree(cp) => (vec![cp], false),
+            BoardState::Boring => (vec![], false),
+        };
+        // not having to move is only an option when nothing has to be answered
+        let mut best = LOST-1;
+        if !threatened {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            best = stand_pat;
+            alpha = alpha.max(stand_pat);
+            moves.extend(patterns::four_moves(board, board.turn).into_iter().map(|(cp, _)| cp));
+            moves.sort();
+            moves.dedup();
+        }
+
+        for cp in moves {
+            self.quiescence_positions += 1;
+            board.make_move(cp);
+            let eval = if self.rules.is_win(board, cp) {
+                WIN
+            } else {
+                from_child(self.quiesce(board, to_child(beta), to_child(alpha), depth_left - 1))
+            };
+            board.unmake_move(cp);
+            best = best.max(eval);
+            alpha = alpha.max(eval);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    /// `minmax` from the root, in an aspiration window around `guess` if there is one.
+    fn root_minmax(&mut self, board: &mut Board, guess: Option<i32>) -> (i32, Option<CellPos>) {
+        if !self.narrow_windows {
+            return self.minmax(0, board, LOST-1, WIN+1);
+        }
+        let mut stats = SearchStats::default();
+        let result = aspiration(guess, &mut stats, |alpha, beta| self.minmax(0, board, alpha, beta), || true);
+        self.stats += stats;
         result
+    }
 
+    /// Iterative deepening up to `self.depth`, stops when the next depth likely won't fit into `target`.
+    fn timed_minmax(&mut self, board: &mut Board, target: Duration, guess: Option<i32>) -> (i32, Option<CellPos>) {
+        // rough time ratio of depth d+1 to depth d
+        const BRANCHING: u32 = 8;
+
+        let max_depth = self.depth;
+        let start = Instant::now();
+        let mut result = (LOST-1, None);
+        for depth in 1..=max_depth {
+            self.depth = depth;
+            let iteration_start = Instant::now();
+            // the previous depth's score is the best guess there is
+            let guess = if depth == 1 { guess } else { Some(result.0) };
+            result = self.root_minmax(board, guess);
+            if start.elapsed() + iteration_start.elapsed() * BRANCHING > target {
+                break;
+            }
+        }
+        self.depth = max_depth;
+        result
     }
 }
 
 impl Actor for BobAI {
     fn next(&mut self, board: &Board) -> CellPos {
-        if board.free_positions().count() == 15*15 {
-            return cell(7, 7);
+        // our score a move ago is a fair guess for this one
+        let guess = self.last_score.take();
+        self.stats = SearchStats::default();
+        if board.free_positions().count() == board_size()*board_size() {
+            return center();
         }
         let mut board = board.clone();
+        self.ordering.age(&board);
         let last_memory_count = self.used_memory;
         let last_computed_positions_count = self.computed_positions;
-        let next_move = self.minmax(0, &mut board, LOST-1, WIN+1);
-        println!("---------BOB EVAL------------");
-        println!("MEMORY USE: {}", self.used_memory-last_memory_count);
-        println!("POSITIONS COUNT: {}", self.computed_positions-last_computed_positions_count);
-        println!("MOVE SCORE: {}", next_move.0);
+        let last_quiescence_count = self.quiescence_positions;
+        let next_move = match self.time_budget {
+            Some(budget) => self.timed_minmax(&mut board, budget.target(), guess),
+            None => self.root_minmax(&mut board, guess)
+        };
+        if self.debug {
+            println!("---------BOB EVAL------------");
+            println!("MEMORY USE: {}", self.used_memory-last_memory_count);
+            println!("POSITIONS COUNT: {}", self.computed_positions-last_computed_positions_count);
+            println!("QUIESCENCE POSITIONS: {}", self.quiescence_positions-last_quiescence_count);
+            println!("MOVE SCORE: {}", describe_score(next_move.0));
+            println!("RE-SEARCHES: {:?}", self.stats);
+        }
+        self.last_score = Some(next_move.0);
         next_move.1.unwrap()
     }
-}
\ No newline at end of file
+    fn last_score(&self) -> Option<i32> {
+        self.last_score
+    }
+    fn set_time_budget(&mut self, budget: TimeBudget) {
+        self.time_budget = Some(budget);
+    }
+    fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+    fn accept_undo(&mut self, _: &Board) -> bool {
+        true
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiesce() {
+        // two blocked threes of White meet at I8, a double four the eval doesn't see
+        let mut board = Board::new();
+        for (white, black) in [((5, 7), (4, 7)), ((6, 7), (8, 3)), ((7, 7), (0, 0)), ((8, 4), (0, 14)), ((8, 5), (14, 0)), ((8, 6), (14, 14))] {
+            board.make_move(cell(white.0, white.1));
+            board.make_move(cell(black.0, black.1));
+        }
+        assert!(!is_win(bobs_shallow_eval(&board, false)));
+        let mut bob = BobAI::new(1);
+        assert_eq!(bob.quiesce(&mut board, LOST-1, WIN+1, QUIESCENCE_DEPTH), WIN-2);
+    }
+}
@@ -1,307 +1,239 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
+use crate::clock::TimeBudget;
+use crate::patterns::{self, Shape, ShapeKind};
 
 use super::*;
+use super::ordering::MoveOrdering;
+use super::verify::{attacker_wins, defender_escapes, VERIFY_DEPTH};
 
 #[derive(Eq, PartialEq, Hash)]
 struct MemoryEntry(u64);
 
 pub struct John {
     memory: HashMap<MemoryEntry, i32>,
+    /// whether a heuristic win or loss claim in this position held up in `confirm`
+    verified: HashMap<MemoryEntry, bool>,
+    /// heuristic claims checked and how many of them were wrong, during the last `next`
+    claims: usize,
+    overturned: usize,
+    ordering: MoveOrdering,
+    /// order candidates with `ordering` instead of taking them as they come
+    pub move_ordering: bool,
+    /// positions searched so far
+    pub nodes: usize,
+    /// null windows for all but the first move of the final loop and aspiration windows at the root
+    pub narrow_windows: bool,
+    pub stats: SearchStats,
     compute: f32,
     mp: f32,
-    count_iter: i32
+    count_iter: i32,
+    pub debug: bool,
+    /// evaluate leaves with this network instead of `bobs_shallow_eval`
+    pub network: Option<Arc<Network>>,
+    /// what counts as a win in the searched lines
+    rules: Rules,
+    /// the game was stopped, every node is a leaf from then on
+    stop: Arc<AtomicBool>,
+    last_score: Option<i32>,
+    last_reason: Option<Reason>,
+    time_budget: Option<TimeBudget>,
+    /// compute units searched per second, measured on the previous timed moves
+    speed: f32
 }
 
+/// The most urgent tactical fact about the position, from the side to move's point of view.
 #[derive(Debug)]
 pub enum BoardState {
     Boring,
-    // DefenseOrCounterAttack(Vec<CellPos>), this one is hell of a pain to evaluate let's not do it
+    /// the opponent has an open three, block it (one of the defences) or make a four first,
+    /// empty if there's nothing that stops it
+    DefendOrCounterFour(Vec<CellPos>),
+    /// playing here makes a four and an open three at once
+    FourThree(CellPos),
+    /// playing here makes two fours at once
+    DoubleFour(CellPos),
+    /// playing here makes an open four
     TwoMoveWin(CellPos),
-    ForcedDefense(Vec<CellPos>),
+    /// the opponent has a four, this is the cell that stops it
+    ForcedDefense(CellPos),
+    /// the opponent has two ways to five, blocking this one isn't enough
     OneMoveLoss(CellPos),
     OneMoveWin(CellPos),
 }
 
-impl BoardState {
-    fn priority(&self) -> u8 {
-        match &self {
-            Self::Boring => 0,
-            // Self::DefenseOrCounterAttack(_) => 1,
-            Self::TwoMoveWin(_) => 2,
-            Self::ForcedDefense(_) => 3,
-            Self::OneMoveLoss(_) => 4,
-            Self::OneMoveWin(_) => 5,
-        }
-    }
-}
-
-
 impl BoardState {
     pub fn compute(board: &Board) -> Self {
-        let mut result = BoardState::Boring;
-        let positions = board.free_positions().filter(|cp| valid_move(board, *cp)).collect::<Vec<_>>();
-
-        let mut update_result = |board_state: BoardState| {
-            if board_state.priority() > result.priority() {
-                result = board_state;
-            }
-            else if board_state.priority() == result.priority() {
-                match board_state {
-                    BoardState::Boring => panic!("You shouldn't set me (no point)."),
-                    // BoardState::DefenseOrCounterAttack(mvs) => {
-                    //     let BoardState::DefenseOrCounterAttack(result_mvs) = &mut result else {
-                    //         panic!();
-                    //     };
-                    //     result_mvs.extend(mvs.into_iter());
-                    // },
-                    BoardState::ForcedDefense(mvs) => {
-                        let BoardState::ForcedDefense(result_mvs) = &mut result else {
-                            panic!();
-                        };
-
-                        result_mvs.extend(mvs.into_iter());
-                    },
-                    _ => return
-                };
-            }
-            
-        };
-
-        'outer: for cp in positions {
-            let dir_count = board.compute_dir_lengths_from(cp);
-
-            for i in 0..8 { // this will evaluate splits twice, idc tho
-                let Some(next) = cp.try_add(dir(i)) else {
-                    continue;
-                };
-                let Some(stone) = board[next] else {
-                    continue;
-                };
-
-                let mut back = 0;
-                let front = dir_count[i];
-
-                if let Some(prev) = cp.try_add(-dir(i)) {
-                    if let Some(prev_stone) = board[prev] {
-                        if prev_stone == stone {
-                            back = dir_count[(i+4)%8];
-                        }
-                    }
-                }
-
-                // ------------ one move win -------------
-                if front+back == 4 && stone == board.turn {
-                    update_result(BoardState::OneMoveWin(cp));
-                    break 'outer;
-                }
-
-                let mut front_bounded = true;
-                let mut back_bounded = true;
-
-                if let Some(next2) = cp.try_add((front+1)*dir(i)) {
-                    front_bounded = board[next2].is_some();
-                }
-                if let Some(prev2) = cp.try_add((back+1)*(-dir(i))) {
-                    back_bounded = board[prev2].is_some();
-                }
-
-
-                // ---------- one move loss ------------
-                if stone != board.turn && front == 4 && back == 0 && !front_bounded {
-                    update_result(BoardState::OneMoveLoss(cp));
-                    continue;
-                }
-
-                // ----------- forced defense -------------
-
-                if stone != board.turn && front+back == 4 {
-                    update_result(BoardState::ForcedDefense(vec![cp]));
-                }
-                
-                // ----------- two move win ------------
-
-                if stone == board.turn && front == 3 && back == 0 && !back_bounded && !front_bounded {
-                    update_result(BoardState::TwoMoveWin(cp));
-                }
-
-                if stone == board.turn && front == 2 && back == 1 && !back_bounded && !front_bounded {
-                    update_result(BoardState::TwoMoveWin(cp));
-                }
-
-
-                // ----------- defense or counter attack ------------
-
-                // if stone != board.turn && front+back == 3 && !front_bounded && !back_bounded {
-                //     let Some(next2) = cp.try_add((front+1)*dir(i)) else {
-                //         panic!("Must be some because it is not front_bounded");
-                //     };
-                //     let Some(prev) = cp.try_add((back+1)*(-dir(i))) else {
-                //         panic!("Must exist because it is not back_bounded");
-                //     };
-                //     assert!(board[prev].is_none());
-                //     assert!(board[next2].is_none());
-
-                //     update_result(BoardState::DefenseOrCounterAttack(vec![next2, prev, cp]));
-                // }
-
-                // if stone == board.turn && front == 3 && front_bounded && !back_bounded {
-                //     let Some(prev) = cp.try_add(-dir(i)) else {
-                //         panic!("Must exist because not backbounded");
-                //     };
-                //     assert!(board[prev].is_none());
-                    
-                //     update_result(BoardState::DefenseOrCounterAttack(vec![cp, prev]));
-                // }
-
-                // if stone == board.turn && front + back == 3
-            }
+        let us = board.turn;
+        let ours = patterns::shapes(board, us);
+        let theirs = patterns::shapes(board, -us);
+        let is_four = |shape: &&Shape| matches!(shape.kind, ShapeKind::OpenFour | ShapeKind::SimpleFour | ShapeKind::BrokenFour);
+        let is_three = |shape: &&Shape| matches!(shape.kind, ShapeKind::OpenThree | ShapeKind::BrokenThree);
+
+        if let Some(four) = ours.iter().find(is_four) {
+            return Self::OneMoveWin(four.completions[0]);
         }
 
-        result
-    }
-}
-
-fn johns_shallow_eval(board: &Board) -> i32 {
-    let positions = board.free_positions().filter(|cp| valid_move(board, *cp)).collect::<Vec<_>>();
-    let mut result = 0;
-    for cp in positions {
-        let dir_count = board.compute_dir_lengths_from(cp);
-
-        for i in 0..8 { // this will evaluate splits twice, idc tho
-            let Some(next) = cp.try_add(dir(i)) else {
-                continue;
-            };
-            let Some(stone) = board[next] else {
-                continue;
-            };
-
-            let mut back = 0;
-            let front = dir_count[i];
-
-            if let Some(prev) = cp.try_add(-dir(i)) {
-                if let Some(prev_stone) = board[prev] {
-                    if prev_stone == stone {
-                        back = dir_count[(i+4)%8];
-                    }
-                }
-            }
+        // the same four may be found along with another one through the same cell, count cells
+        let mut their_fives = theirs.iter().filter(is_four).flat_map(|four| four.completions.iter().copied()).collect::<Vec<_>>();
+        their_fives.sort();
+        their_fives.dedup();
+        match their_fives[..] {
+            [] => {}
+            [cp] => return Self::ForcedDefense(cp),
+            [cp, ..] => return Self::OneMoveLoss(cp),
+        }
 
-            let mut front_bounded = true;
-            let mut back_bounded = true;
+        if let Some(three) = ours.iter().find(is_three) {
+            return Self::TwoMoveWin(three.completions[0]);
+        }
 
-            if let Some(next2) = cp.try_add((front+1)*dir(i)) {
-                front_bounded = board[next2].is_some();
+        let four_moves = patterns::four_moves(board, us);
+        let open_three_moves = ours.iter()
+            .filter(|shape| shape.kind == ShapeKind::OpenTwo)
+            .flat_map(|two| two.completions.iter().map(|cp| (*cp, two.dir)))
+            .collect::<Vec<_>>();
+        // two fours through the same cell that share their five are stopped by one stone
+        if let Some(&cp) = patterns::double_four_moves(board, us).first() {
+            return Self::DoubleFour(cp);
+        }
+        for &(cp, i) in &four_moves {
+            if open_three_moves.iter().any(|&(other, j)| other == cp && j != i) {
+                return Self::FourThree(cp);
             }
-            if let Some(prev2) = cp.try_add((back+1)*(-dir(i))) {
-                back_bounded = board[prev2].is_some();
-            }    
+        }
 
-            if !front_bounded && !back_bounded && back+front == 3 {
-                if stone == board.turn {
-                    result += 200;
-                }
-                else {
-                    result -= 50;
-                }
-            }
-            if !front_bounded && back_bounded && back+front == 3 {
-                if stone == board.turn {
-                    result += 30;
-                }
-                else {
-                    result -= 20;
-                }
-            }
-            if !front_bounded && !back_bounded && back+front == 2 {
-                if stone == board.turn {
-                    result += 30;
-                }
-                else {
-                    result -= 20;
-                }
-            }
-            if !front_bounded && back_bounded && back+front == 2 {
-                if stone == board.turn {
-                    result += 10;
-                }
-                else {
-                    result -= 8;
-                }
-            }
+        let mut their_threes = theirs.iter().filter(is_three).peekable();
+        if their_threes.peek().is_none() {
+            return Self::Boring;
         }
+        // one stone has to stop every three, otherwise only fours of our own keep us going
+        let mut defences = their_threes.next().unwrap().defences.clone();
+        for three in their_threes {
+            defences.retain(|cp| three.defences.contains(cp));
+        }
+        defences.extend(four_moves.iter().map(|(cp, _)| *cp));
+        defences.sort();
+        defences.dedup();
+        Self::DefendOrCounterFour(defences)
     }
-    result
 }
 
+/// What a `bobs_shallow_eval` win or loss is worth once `confirm` found a way out of it.
+const UNPROVEN: i32 = 5_000;
+
+/// Why `minimax` settled on its score.
 #[derive(Debug, Clone, Copy)]
-enum Reason {
+pub enum Reason {
     AllLosingMoves,
     WinningMove,
     BobsEval,
     OneMoveWin,
     TwoMove,
     OneMoveLoss,
+    /// a four and an open three or two fours at once
+    FourThree,
     ForcedLoss,
-    ForcedDefense
+    ForcedDefense,
+    /// the line filled the board without a five
+    Draw
 }
 
 impl John {
     pub fn new(compute: f32, mp: f32, count_iter: i32) -> Self {
         Self {
             memory: Default::default(),
+            verified: Default::default(),
+            claims: 0,
+            overturned: 0,
+            ordering: MoveOrdering::new(),
+            move_ordering: true,
+            nodes: 0,
+            narrow_windows: true,
+            stats: SearchStats::default(),
             compute,
             mp,
-            count_iter
+            count_iter,
+            debug: true,
+            network: None,
+            rules: Rules::default(),
+            stop: Default::default(),
+            last_score: None,
+            last_reason: None,
+            time_budget: None,
+            speed: 150_000.
         }
     }
 
     pub fn minimax(&mut self, board: &mut Board, mut alpha: i32, beta: i32, mut comp_rem: f32) -> (i32, Option<CellPos>, Reason) {
-        comp_rem -= (15*15) as f32;        
+        comp_rem -= (board_size()*board_size()) as f32;        
+        self.nodes += 1;
 
-        let moves_to_explore;
+        let mut moves_to_explore;
 
-        match BoardState::compute(board) {
+        let candidates = match BoardState::compute(board) {
             BoardState::OneMoveWin(cp) => return (WIN, Some(cp), Reason::OneMoveWin),
-            BoardState::TwoMoveWin(cp) => return (WIN, Some(cp), Reason::TwoMove),
-            BoardState::OneMoveLoss(cp) => return (LOST, Some(cp), Reason::OneMoveLoss),
-            BoardState::ForcedDefense(mut mvs) => {
-                mvs.sort();
-                mvs.dedup();
-                assert!(!mvs.is_empty());
-                if mvs.len() > 1 {
-                    return (LOST, Some(mvs[0]), Reason::ForcedLoss);
-                }
-                board.make_move(mvs[0]);
-                let result = (self.minimax(board, -beta, -alpha, comp_rem));
-                let result = (-result.0, Some(mvs[0]), result.2);
-                board.unmake_move(mvs[0]);
+            BoardState::TwoMoveWin(cp) if self.confirm(board, WIN) => return (WIN-2, Some(cp), Reason::TwoMove),
+            BoardState::DoubleFour(cp) if self.confirm(board, WIN) => return (WIN-2, Some(cp), Reason::FourThree),
+            BoardState::FourThree(cp) if self.confirm(board, WIN) => return (WIN-4, Some(cp), Reason::FourThree),
+            // a counter-four gets in the way, search it like any other position
+            BoardState::TwoMoveWin(_) | BoardState::DoubleFour(_) | BoardState::FourThree(_) => None,
+            BoardState::OneMoveLoss(cp) => return (LOST+1, Some(cp), Reason::OneMoveLoss),
+            BoardState::ForcedDefense(cp) => {
+                board.make_move(cp);
+                let result = self.minimax(board, to_child(beta), to_child(alpha), comp_rem);
+                let result = (from_child(result.0), Some(cp), result.2);
+                board.unmake_move(cp);
                 return result;
             },
-            BoardState::Boring => {
-                if comp_rem < 0. {
-                    let bobs_eval = if let Some(result) = self.memory.get(&MemoryEntry(board.hash)) {
-                        *result
+            // they get an open four whatever we do
+            BoardState::DefendOrCounterFour(mvs) if mvs.is_empty() => return (LOST+3, None, Reason::ForcedLoss),
+            BoardState::DefendOrCounterFour(mvs) => Some(mvs),
+            BoardState::Boring => None,
+        };
+        if comp_rem < 0. || self.stop.load(Ordering::Relaxed) {
+            let bobs_eval = if let Some(result) = self.memory.get(&MemoryEntry(board.hash)) {
+                *result
+            }
+            else {
+                let result = match &board.accumulator {
+                    Some(accumulator) => accumulator.evaluate(board.turn),
+                    None => match bobs_shallow_eval(board, false) {
+                        WIN if !self.confirm(board, WIN) => UNPROVEN,
+                        LOST if !self.confirm(board, LOST) => -UNPROVEN,
+                        eval => eval
                     }
-                    else {
-                        let result = bobs_shallow_eval(board, false);
-                        self.memory.insert(MemoryEntry(board.hash), result);
-                        result
-                    };
-                    return (bobs_eval, None, Reason::BobsEval);
-                }
-                moves_to_explore = board.free_positions().filter(|cp| valid_move(board, *cp)).collect::<Vec<_>>()
-            },
+                };
+                self.memory.insert(MemoryEntry(board.hash), result);
+                result
+            };
+            return (bobs_eval, None, Reason::BobsEval);
+        }
+        let all_moves = board.candidates().collect::<Vec<_>>();
+        // roughly the plies of search left, for the history table
+        let depth = (comp_rem / (board_size()*board_size()) as f32).max(1.).log2() as i32;
+        // the few defences left shouldn't get the compute of all the pruned moves, the search would never end
+        let split = match &candidates {
+            Some(_) => all_moves.len().max(1) as f32,
+            None => 1.
+        };
+        moves_to_explore = candidates.unwrap_or(all_moves);
+        if moves_to_explore.is_empty() {
+            return (0, None, Reason::Draw);
+        }
+        if self.move_ordering {
+            self.ordering.order(board, &mut moves_to_explore);
         }
 
         let mp = self.mp;
         let count_iter = self.count_iter;
-        let mut cur_comp = comp_rem/(mp.powi(count_iter+1))/moves_to_explore.len() as f32;
+        let mut cur_comp = comp_rem/(mp.powi(count_iter+1))/(moves_to_explore.len() as f32).max(split);
         let mut moves_to_explore = moves_to_explore.into_iter().map(|cp| {
             (0, cp)
         }).collect::<Vec<_>>();
-        moves_to_explore.sort_by_key(|x| x.0);
-        moves_to_explore.reverse();
 
 
         for _ in 0..count_iter {
@@ -310,33 +242,37 @@ impl John {
             //     println!("{cur_comp}/{comp_rem}");
             // }
 
-            if cur_comp < 15.*15. / mp {
+            if cur_comp < (board_size()*board_size()) as f32 / mp {
                 continue;
             }
 
             let mvs_count = moves_to_explore.len();
-            moves_to_explore = moves_to_explore.into_iter().enumerate().map(|(i, (_leval, cp))| {
+            let explored = moves_to_explore.into_iter().enumerate().map(|(i, (_leval, cp))| {
                 let comp = cur_comp+(cur_comp*mp-cur_comp)*((mvs_count-i) as f32/mvs_count as f32);
                 board.make_move(cp);
-                if board.check_win_from(cp) {
+                if self.rules.is_win(board, cp) {
                     board.unmake_move(cp);
                     return (WIN, cp);
                 }
-                let result = (-self.minimax(board, -beta, -alpha, comp).0, cp);
+                let result = (from_child(self.minimax(board, to_child(beta), to_child(alpha), comp).0), cp);
                 board.unmake_move(cp);
 
                 result
-            }).filter(|(eval, cp)| *eval!=LOST).collect::<Vec<_>>();
+            }).collect::<Vec<_>>();
+            // when everything loses, hold out for as long as possible
+            let slowest_loss = explored.iter().copied().max_by_key(|x| x.0);
+            moves_to_explore = explored.into_iter().filter(|(eval, _)| !is_loss(*eval)).collect::<Vec<_>>();
 
             if moves_to_explore.is_empty() {
-                return (LOST, None, Reason::AllLosingMoves);
+                let (eval, cp) = slowest_loss.unwrap();
+                return (eval, Some(cp), Reason::AllLosingMoves);
             }
 
             // need to make sure that LOST and WIN are not present (if WIN is possible we should just return it)
-            moves_to_explore.sort_by_key(|x| x.0);
-            moves_to_explore.reverse();
-            if moves_to_explore[0].0 == WIN {
-                return (WIN, Some(moves_to_explore[0].1), Reason::WinningMove);
+            // stable, so equal scores keep the order they came in
+            moves_to_explore.sort_by_key(|x| std::cmp::Reverse(x.0));
+            if is_win(moves_to_explore[0].0) {
+                return (moves_to_explore[0].0, Some(moves_to_explore[0].1), Reason::WinningMove);
             }
         }
 
@@ -345,17 +281,26 @@ impl John {
         let mut best_eval = LOST-1;
         let mut cur_reason = Reason::BobsEval;
 
-        let cur_comp = comp_rem/moves_to_explore.len() as f32;
+        let cur_comp = comp_rem/(moves_to_explore.len() as f32).max(split);
 
         let mv_count = moves_to_explore.len();
         for (i, (_, cp)) in moves_to_explore.into_iter().enumerate() {
             let bonus_comp = (cur_comp*mp-cur_comp)*(mv_count-i) as f32/mv_count as f32;
             board.make_move(cp);
-            if board.check_win_from(cp) {
+            if self.rules.is_win(board, cp) {
+                board.unmake_move(cp);
                 return (WIN, Some(cp), Reason::WinningMove);
             }
-            let mut eval = self.minimax(board, -beta, -alpha, cur_comp+bonus_comp);
-            eval.0 = -eval.0;
+            // the first move is likely the best, the others only have to be shown worse than it
+            let null_window = i > 0 && self.narrow_windows;
+            let (upper, lower) = if null_window { (alpha+1, alpha) } else { (beta, alpha) };
+            let mut eval = self.minimax(board, to_child(upper), to_child(lower), cur_comp+bonus_comp);
+            eval.0 = from_child(eval.0);
+            if null_window && eval.0 > alpha && eval.0 < beta {
+                self.stats.researches += 1;
+                eval = self.minimax(board, to_child(beta), to_child(alpha), cur_comp+bonus_comp);
+                eval.0 = from_child(eval.0);
+            }
             board.unmake_move(cp);
 
             if eval.0 > best_eval {
@@ -366,26 +311,199 @@ impl John {
                 alpha = eval.0;
                 best_move = Some(cp);
                 if alpha >= beta {
+                    self.ordering.cutoff(board, cp, depth);
                     return (best_eval, best_move, eval.2);
                 }
             }
         }
 
+        if let Some(cp) = best_move {
+            self.ordering.best(board, cp);
+        }
         (best_eval, best_move, cur_reason)
     }
+    /// Checks a heuristic `WIN` or `LOST` for the side to move with a short search of fours and threats.
+    fn confirm(&mut self, board: &mut Board, claim: i32) -> bool {
+        if let Some(holds) = self.verified.get(&MemoryEntry(board.hash)) {
+            return *holds;
+        }
+        let holds = if claim == WIN {
+            attacker_wins(board, VERIFY_DEPTH)
+        }
+        else {
+            !defender_escapes(board, VERIFY_DEPTH)
+        };
+        self.claims += 1;
+        if !holds {
+            self.overturned += 1;
+        }
+        self.verified.insert(MemoryEntry(board.hash), holds);
+        holds
+    }
+    /// What decided the last move returned by `next`.
+    pub fn last_reason(&self) -> Option<Reason> {
+        self.last_reason
+    }
 }
 
 impl Actor for John {
     fn next(&mut self, board: &Board) -> CellPos {
         let mut board = board.clone();
-        if board.free_positions().count() == 15*15 {
-            return cell(7, 7);
+        // our score a move ago is a fair guess for this one
+        let guess = self.last_score.take();
+        self.last_reason = None;
+        self.stats = SearchStats::default();
+        self.claims = 0;
+        self.overturned = 0;
+        self.ordering.age(&board);
+        if board.free_positions().count() == board_size()*board_size() {
+            return center();
+        }
+        if let Some(network) = &self.network {
+            board.attach_network(network.clone());
         }
 
-        let result = self.minimax(&mut board, LOST-1, WIN+1, self.compute);
+        // with a clock the compute is whatever fits into the time we want to spend
+        let compute = match self.time_budget {
+            Some(budget) => budget.target().as_secs_f32() * self.speed,
+            None => self.compute
+        };
+        let start = Instant::now();
+        let target = self.time_budget.map(|budget| budget.target());
+        let speed = self.speed;
+        let mut searched = 0.;
+        let result = if self.narrow_windows {
+            let mut stats = SearchStats::default();
+            // re-searches share the time of the move, each gets what the ones before it left
+            let (_, result) = aspiration(guess, &mut stats, |alpha, beta| {
+                let compute = match target {
+                    // still enough to look at every move once
+                    Some(target) => (target.saturating_sub(start.elapsed()).as_secs_f32() * speed).max((2*board_size()*board_size()) as f32),
+                    None => compute
+                };
+                searched += compute;
+                let result = self.minimax(&mut board, alpha, beta, compute);
+                (result.0, result)
+            }, || target.is_none_or(|target| start.elapsed() < target));
+            self.stats += stats;
+            result
+        }
+        else {
+            searched = compute;
+            self.minimax(&mut board, LOST-1, WIN+1, compute)
+        };
+        let elapsed = start.elapsed().as_secs_f32();
+        if self.time_budget.is_some() && elapsed > 0.05 {
+            self.speed = (self.speed + searched / elapsed) / 2.;
+        }
 
-        println!("{:?}", result);
+        if self.debug {
+            println!("{:?} {}", result, describe_score(result.0));
+            println!("heuristic claims overturned: {}/{}", self.overturned, self.claims);
+            println!("re-searches: {:?}", self.stats);
+        }
+        self.last_score = Some(result.0);
+        self.last_reason = Some(result.2);
 
-        result.1.unwrap()
+        // every move loses, still have to play something
+        result.1.unwrap_or_else(|| board.candidates().next().unwrap())
+    }
+    fn last_score(&self) -> Option<i32> {
+        self.last_score
+    }
+    fn set_time_budget(&mut self, budget: TimeBudget) {
+        self.time_budget = Some(budget);
+    }
+    fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
     }
-}
\ No newline at end of file
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+    /// The built-in engines let the human they play take moves back.
+    fn accept_undo(&mut self, _: &Board) -> bool {
+        true
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::board::board_with;
+    use crate::clock::TimeControl;
+
+    use super::*;
+
+    /// `BoardState` with White to move, see `board_with`.
+    fn state(white: &[(usize, usize)], black: &[(usize, usize)]) -> BoardState {
+        let cells = |stones: &[(usize, usize)]| stones.iter().map(|&(x, y)| cell(x, y)).collect::<Vec<_>>();
+        BoardState::compute(&board_with(&cells(white), &cells(black)))
+    }
+
+    #[test]
+    fn test_board_state() {
+        assert!(matches!(state(&[(7, 7)], &[(8, 8)]), BoardState::Boring));
+        assert!(matches!(state(&[(5, 7), (6, 7), (7, 7), (8, 7)], &[]), BoardState::OneMoveWin(_)));
+
+        // Black's four is blocked on the left, only J8 stops it
+        let four = [(5, 7), (6, 7), (7, 7), (8, 7)];
+        assert!(matches!(state(&[(4, 7)], &four), BoardState::ForcedDefense(cp) if cp == cell(9, 7)));
+        assert!(matches!(state(&[], &four), BoardState::OneMoveLoss(_)));
+
+        assert!(matches!(state(&[(5, 7), (6, 7), (7, 7)], &[]), BoardState::TwoMoveWin(cp) if cp == cell(4, 7) || cp == cell(8, 7)));
+
+        // blocked threes across two lines
+        let double = state(&[(5, 7), (6, 7), (7, 7), (8, 4), (8, 5), (8, 6)], &[(4, 7), (8, 3)]);
+        assert!(matches!(double, BoardState::DoubleFour(cp) if cp == cell(8, 7)));
+        // X.X.X.X, D8 leaves B8 and F8 for the five
+        let same_line = state(&[(0, 9), (2, 9), (4, 9), (6, 9)], &[]);
+        assert!(matches!(same_line, BoardState::DoubleFour(cp) if cp == cell(3, 9)));
+
+        let four_three = state(&[(5, 7), (6, 7), (7, 7), (8, 5), (8, 6), (9, 2)], &[(4, 7)]);
+        assert!(matches!(four_three, BoardState::FourThree(cp) if cp == cell(8, 7)));
+
+        // one open three of Black's, either end stops it
+        let three = [(5, 7), (6, 7), (7, 7)];
+        assert!(matches!(state(&[(10, 10)], &three), BoardState::DefendOrCounterFour(mvs) if mvs == [cell(4, 7), cell(8, 7)]));
+        // two that no stone stops at once
+        let threes = [(5, 7), (6, 7), (7, 7), (10, 2), (10, 3), (10, 4)];
+        assert!(matches!(state(&[], &threes), BoardState::DefendOrCounterFour(mvs) if mvs.is_empty()));
+    }
+
+    #[test]
+    fn test_re_searches_share_the_budget() {
+        let board = board_with(&[cell(7, 7), cell(8, 7)], &[cell(7, 8)]);
+        let mut john = John::new(1e6, 2., 4);
+        john.debug = false;
+        // a guess far from the real score, every window around it fails
+        john.last_score = Some(20_000);
+        john.speed = 50_000.;
+        let increment = Duration::from_millis(300);
+        let remaining = Duration::from_millis(600);
+        john.set_time_budget(TimeBudget { control: TimeControl::Fischer { base: remaining, increment }, remaining });
+        let start = Instant::now();
+        john.next(&board);
+        assert!(john.stats.fail_lows > 0);
+        assert!(start.elapsed() < remaining, "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_full_board() {
+        // runs of at most two stones in every direction, three corners left empty
+        let (mut white, mut black) = (vec![], vec![]);
+        for x in 0..board_size() {
+            for y in 0..board_size() {
+                if [(0, 0), (0, 14), (14, 14)].contains(&(x, y)) {
+                    continue;
+                }
+                if (x / 2 + y) % 2 == 0 { white.push(cell(x, y)) } else { black.push(cell(x, y)) }
+            }
+        }
+        let board = board_with(&white, &black);
+        assert_eq!(board.free_positions().count(), 3);
+
+        let mut john = John::new(1e6, 2., 4);
+        john.debug = false;
+        assert!(board[john.next(&board)].is_none());
+    }
+}
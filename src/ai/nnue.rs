@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::io::{self, Read, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::board::MAX_BOARD_SIZE;
+
+use super::*;
+
+/// (cell, stone) pairs, stone is relative to the perspective (own / opponent's).
+/// Cells are laid out for the largest board, smaller boards use the lower left corner.
+const FEATURES: usize = MAX_BOARD_SIZE * MAX_BOARD_SIZE * 2;
+/// clipped relu ceiling of the first layer
+const QA: i32 = 255;
+/// output weights quantization
+const QB: i32 = 64;
+/// network output of 1.0 ~ this many eval points
+const SCALE: i32 = 400;
+/// anything wider is a corrupt file rather than a network
+const MAX_HIDDEN: usize = 4096;
+
+const MAGIC: &[u8; 4] = b"GNN1";
+
+/// Weights of a 722 -> hidden (x2 perspectives) -> 1 network.
+///
+/// File layout (little endian): magic `GNN1`, `u32` hidden size,
+/// `i16` feature weights `[FEATURES][hidden]`, `i16` feature biases `[hidden]`,
+/// `i16` output weights `[2 * hidden]` (side to move first), `i32` output bias.
+#[derive(Debug)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a gomoku network file"));
+        }
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        let hidden = u32::from_le_bytes(buf) as usize;
+        if !(1..=MAX_HIDDEN).contains(&hidden) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("hidden size {} out of range", hidden)));
+        }
+
+        let mut read_i16s = |count: usize| -> io::Result<Vec<i16>> {
+            let mut bytes = vec![0; count * 2];
+            reader.read_exact(&mut bytes)?;
+            Ok(bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+        };
+        let feature_weights = read_i16s(FEATURES * hidden)?;
+        let feature_biases = read_i16s(hidden)?;
+        let output_weights = read_i16s(2 * hidden)?;
+
+        reader.read_exact(&mut buf)?;
+        let output_bias = i32::from_le_bytes(buf);
+
+        Ok(Self { hidden, feature_weights, feature_biases, output_weights, output_bias })
+    }
+
+    fn feature_index(perspective: Stone, cp: CellPos, stone: Stone) -> usize {
+        (cp.x() * MAX_BOARD_SIZE + cp.y()) * 2 + (stone != perspective) as usize
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+}
+
+/// First layer outputs for both perspectives, kept up to date by `Board::make_move` / `Board::unmake_move`.
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    /// indexed by `Stone as usize`, i32 since enough stones add up past i16
+    values: [Vec<i32>; 2],
+}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut result = Self {
+            values: [0, 1].map(|_| network.feature_biases.iter().map(|&bias| bias as i32).collect()),
+            network,
+        };
+        for x in 0..board_size() {
+            for y in 0..board_size() {
+                if let Some(stone) = board[cell(x, y)] {
+                    result.add(cell(x, y), stone);
+                }
+            }
+        }
+        result
+    }
+
+    pub fn add(&mut self, cp: CellPos, stone: Stone) {
+        for perspective in [Stone::Black, Stone::White] {
+            let weights = self.network.weights(Network::feature_index(perspective, cp, stone));
+            for (value, weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value += *weight as i32;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, cp: CellPos, stone: Stone) {
+        for perspective in [Stone::Black, Stone::White] {
+            let weights = self.network.weights(Network::feature_index(perspective, cp, stone));
+            for (value, weight) in self.values[perspective as usize].iter_mut().zip(weights) {
+                *value -= *weight as i32;
+            }
+        }
+    }
+
+    /// Score from the point of view of `turn`, kept well inside (LOST, WIN).
+    pub fn evaluate(&self, turn: Stone) -> i32 {
+        let (us, them) = (&self.values[turn as usize], &self.values[(-turn) as usize]);
+        // 2 * hidden products of up to 255 * i16 each, past i32 for wide networks
+        let mut sum = 0i64;
+        for (i, value) in us.iter().chain(them.iter()).enumerate() {
+            let activated = (*value).clamp(0, QA);
+            sum += (activated * self.network.output_weights[i] as i32) as i64;
+        }
+        let result = (sum + self.network.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        result.clamp(LOST as i64 / 2, WIN as i64 / 2) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_network() -> Network {
+        let hidden = 8;
+        let mut rng = rand::thread_rng();
+        let mut random_i16s = |count: usize| (0..count).map(|_| rng.gen_range(-64..64)).collect::<Vec<i16>>();
+        Network {
+            hidden,
+            feature_weights: random_i16s(FEATURES * hidden),
+            feature_biases: random_i16s(hidden),
+            output_weights: random_i16s(2 * hidden),
+            output_bias: 3,
+        }
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let mut board = Board::new();
+        board.attach_network(Arc::new(test_network()));
+        board.make_move(cell(7, 7));
+        board.make_move(cell(7, 8));
+        board.make_move(cell(3, 2));
+        board.unmake_move(cell(3, 2));
+        board.make_move(cell(8, 8));
+
+        let incremental = board.accumulator.clone().unwrap();
+        let refreshed = Accumulator::new(incremental.network.clone(), &board);
+
+        assert_eq!(incremental.values, refreshed.values);
+        assert_eq!(incremental.evaluate(board.turn), refreshed.evaluate(board.turn));
+    }
+
+    #[test]
+    fn test_load() {
+        let network = test_network();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((network.hidden as u32).to_le_bytes());
+        for value in network.feature_weights.iter().chain(&network.feature_biases).chain(&network.output_weights) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(network.output_bias.to_le_bytes());
+
+        let loaded = Network::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.feature_weights, network.feature_weights);
+        assert_eq!(loaded.output_weights, network.output_weights);
+        assert_eq!(loaded.output_bias, network.output_bias);
+    }
+
+    #[test]
+    fn test_load_rejects_huge_hidden() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        let err = Network::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,7 +1,8 @@
 use super::*;
 
 pub struct NoobAI {
-    pub depth: usize
+    pub depth: usize,
+    pub rules: Rules,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -17,8 +18,8 @@ impl NoobAI {
             return None;
         }
         let mut eval = None;
-        for x in 0..15 {
-            for y in 0..15 {
+        for x in 0..board_size() {
+            for y in 0..board_size() {
                 let cp = cell(x, y);
 
                 let mut has_neigbor = false;
@@ -32,7 +33,7 @@ impl NoobAI {
                 if !has_neigbor || !board.make_move(cp) {
                     continue;
                 }
-                let win = board.check_win_from(cp);
+                let win = self.rules.is_win(board, cp);
 
                 if win {
                     board.unmake_move(cp);
@@ -68,8 +69,8 @@ impl NoobAI {
 
 impl Actor for NoobAI {
     fn next(&mut self, board: &Board) -> CellPos {
-        if board.free_positions().count() == 15*15 {
-            return cell(7, 7);
+        if board.free_positions().count() == board_size()*board_size() {
+            return center();
         }
         let mut board = board.clone();
         match self.minmax(0, &mut board) {
@@ -77,6 +78,12 @@ impl Actor for NoobAI {
             None => board.free_positions().next().unwrap()
         }
     }
+    fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+    fn accept_undo(&mut self, _: &Board) -> bool {
+        true
+    }
 
 }
 
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use crate::board::MAX_BOARD_SIZE;
+
+use super::*;
+
+/// Ordering bonus of a killer move, above everything but fours made or blocked.
+const KILLER: i32 = 20_000;
+/// History never outweighs a killer.
+const MAX_HISTORY: i32 = KILLER - 1;
+
+/// Score of the stones next to `cp` along every line, own ones count a bit more than blocking.
+/// Much cheaper than an eval, it only looks at the runs touching the cell.
+pub(crate) fn static_score(board: &Board, cp: CellPos) -> i32 {
+    const OWN: [i32; 5] = [0, 10, 100, 2_000, 200_000];
+    const THEIRS: [i32; 5] = [0, 8, 80, 1_500, 100_000];
+
+    let run = |stone: Stone, i: usize| {
+        (1..).take_while(|&k| cp.try_add(k * dir(i)).is_some_and(|pos| board[pos] == Some(stone))).count()
+    };
+    let mut score = 0;
+    for i in 0..4 {
+        for (stone, table) in [(board.turn, &OWN), (-board.turn, &THEIRS)] {
+            let len = run(stone, i) + run(stone, i + 4);
+            score += table[len.min(4)];
+        }
+    }
+    score
+}
+
+/// Move ordering shared by the engines: the hash move first, then static pattern scores with a bonus
+/// for the killers of the same ply and the history of cutoffs. Plies are counted from the start of the game,
+/// so nothing has to be reset between moves.
+#[derive(Default)]
+pub(crate) struct MoveOrdering {
+    /// best move found in a position, with the ply of the position
+    hash_moves: HashMap<u64, (CellPos, usize)>,
+    /// two most recent moves that caused a cutoff at each ply
+    killers: HashMap<usize, [Option<CellPos>; 2]>,
+    /// cutoffs weighted by depth, indexed by `x * MAX_BOARD_SIZE + y`
+    history: Vec<i32>,
+}
+
+impl MoveOrdering {
+    pub(crate) fn new() -> Self {
+        Self { history: vec![0; MAX_BOARD_SIZE * MAX_BOARD_SIZE], ..Default::default() }
+    }
+
+    fn key(&self, board: &Board, cp: CellPos) -> i32 {
+        if self.hash_moves.get(&board.hash).is_some_and(|(best, _)| *best == cp) {
+            return i32::MAX;
+        }
+        let ply = board.history().len();
+        let killer = match self.killers.get(&ply) {
+            Some(killers) if killers.contains(&Some(cp)) => KILLER,
+            _ => 0,
+        };
+        static_score(board, cp) + killer + self.history[cp.x() * MAX_BOARD_SIZE + cp.y()].min(MAX_HISTORY)
+    }
+
+    /// Sorts `moves` best first.
+    pub(crate) fn order(&self, board: &Board, moves: &mut [CellPos]) {
+        moves.sort_by_cached_key(|cp| std::cmp::Reverse(self.key(board, *cp)));
+    }
+
+    /// `cp` refuted the position, `depth` is how much search was left below it.
+    pub(crate) fn cutoff(&mut self, board: &Board, cp: CellPos, depth: i32) {
+        let killers = self.killers.entry(board.history().len()).or_default();
+        if killers[0] != Some(cp) {
+            killers[1] = killers[0];
+            killers[0] = Some(cp);
+        }
+        self.history[cp.x() * MAX_BOARD_SIZE + cp.y()] += depth * depth;
+        self.best(board, cp);
+    }
+
+    pub(crate) fn best(&mut self, board: &Board, cp: CellPos) {
+        self.hash_moves.insert(board.hash, (cp, board.history().len()));
+    }
+
+    /// Old history is worth less in the next search from `root`, and the moves of plies before it
+    /// won't be asked for again.
+    pub(crate) fn age(&mut self, root: &Board) {
+        for entry in &mut self.history {
+            *entry /= 2;
+        }
+        let ply = root.history().len();
+        self.hash_moves.retain(|_, (_, best_ply)| *best_ply >= ply);
+        self.killers.retain(|killer_ply, _| *killer_ply >= ply);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let mut board = Board::new();
+        for x in 5..9 {
+            board.make_move(cell(x, 7));
+            board.make_move(cell(x, 3));
+        }
+        let mut ordering = MoveOrdering::new();
+        let mut moves = vec![cell(12, 12), cell(4, 3), cell(9, 7), cell(6, 8)];
+        ordering.order(&board, &mut moves);
+        // making five beats blocking it
+        assert_eq!(moves[..2], [cell(9, 7), cell(4, 3)]);
+
+        ordering.cutoff(&board, cell(6, 8), 3);
+        ordering.order(&board, &mut moves);
+        assert_eq!(moves[0], cell(6, 8));
+
+        // nothing of this ply is kept once the game moved on
+        board.make_move(cell(6, 8));
+        ordering.age(&board);
+        assert!(ordering.hash_moves.is_empty() && ordering.killers.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use super::*;
+
+/// Plies the verification follows a chain of threats for before giving up on it.
+pub(crate) const VERIFY_DEPTH: usize = 8;
+
+/// Whether the side to move wins by threats alone: fours, or the open four / four-three / double four
+/// `BoardState` found, with every counter-four of the opponent answered along the way.
+/// Giving up after `depth` plies counts as not winning.
+pub(crate) fn attacker_wins(board: &mut Board, depth: usize) -> bool {
+    let mut moves = match BoardState::compute(board) {
+        BoardState::OneMoveWin(_) => return true,
+        BoardState::OneMoveLoss(_) => return false,
+        // blocking hands the threat over, unless the block is a four itself
+        BoardState::ForcedDefense(cp) => {
+            if depth == 0 {
+                return false;
+            }
+            board.make_move(cp);
+            let wins = !defender_escapes(board, depth - 1);
+            board.unmake_move(cp);
+            return wins;
+        }
+        BoardState::TwoMoveWin(cp) | BoardState::DoubleFour(cp) | BoardState::FourThree(cp) => vec![cp],
+        BoardState::DefendOrCounterFour(_) | BoardState::Boring => vec![],
+    };
+    if depth == 0 {
+        return false;
+    }
+    moves.extend(patterns::four_moves(board, board.turn).into_iter().map(|(cp, _)| cp));
+    moves.sort();
+    moves.dedup();
+
+    moves.into_iter().any(|cp| {
+        board.make_move(cp);
+        let wins = board.check_win_from(cp) || !defender_escapes(board, depth - 1);
+        board.unmake_move(cp);
+        wins
+    })
+}
+
+/// Whether the side to move survives the threat the opponent just made, by blocking it or by fours of its own.
+/// Giving up after `depth` plies counts as surviving.
+pub(crate) fn defender_escapes(board: &mut Board, depth: usize) -> bool {
+    let moves = match BoardState::compute(board) {
+        BoardState::OneMoveWin(_) => return true,
+        BoardState::OneMoveLoss(_) => return false,
+        BoardState::ForcedDefense(cp) => vec![cp],
+        BoardState::DefendOrCounterFour(mvs) => mvs,
+        // there's no threat left to answer, or one of our own that's faster
+        _ => return true,
+    };
+    if depth == 0 {
+        return true;
+    }
+
+    moves.into_iter().any(|cp| {
+        board.make_move(cp);
+        let escapes = board.check_win_from(cp) || !attacker_wins(board, depth - 1);
+        board.unmake_move(cp);
+        escapes
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::board_with;
+
+    use super::*;
+
+    #[test]
+    fn test_counter_four() {
+        // I8 makes a four and an open three
+        let white = [(5, 7), (6, 7), (7, 7), (8, 5), (8, 6), (9, 2)].map(|(x, y)| cell(x, y));
+        let mut board = board_with(&white, &[cell(4, 7)]);
+        assert!(matches!(BoardState::compute(&board), BoardState::FourThree(_)));
+        assert!(attacker_wins(&mut board, VERIFY_DEPTH));
+
+        // but here blocking the four at J8 makes a four for Black, and White loses the tempo
+        let mut board = board_with(&white, &[(4, 7), (9, 3), (9, 4), (9, 5)].map(|(x, y)| cell(x, y)));
+        assert!(matches!(BoardState::compute(&board), BoardState::FourThree(_)));
+        assert!(!attacker_wins(&mut board, VERIFY_DEPTH));
+    }
+}
@@ -0,0 +1,44 @@
+use rand::seq::IteratorRandom;
+
+use crate::game_manager::{GameResult, Turn, take_turn};
+use crate::rules::Rules;
+
+use super::*;
+
+/// Plays the game to the end on the calling thread, no sleeping or locking like in `GameManager`.
+/// Actor returning an illegal move forfeits immediately (engines are not supposed to do that).
+pub fn play_game(board: &mut Board, black_actor: &mut dyn Actor, white_actor: &mut dyn Actor) -> GameResult {
+    let rules = Rules::default();
+    loop {
+        let turn = match board.turn {
+            Stone::Black => take_turn(board, black_actor, white_actor),
+            Stone::White => take_turn(board, white_actor, black_actor),
+        };
+        let next_move = match turn {
+            Turn::Move(next_move) => next_move,
+            // no takebacks in engine games, and no clock to run out
+            Turn::Undo | Turn::Again => continue,
+            Turn::Over(result) => return result,
+        };
+        if let Err(illegal_move) = rules.validate(board, next_move) {
+            return GameResult::Forfeit { loser: board.turn, reason: format!("illegal move, {}", illegal_move) };
+        }
+        board.make_move(next_move);
+        if rules.is_win(board, next_move) {
+            return GameResult::win(-board.turn);
+        }
+    }
+}
+
+/// Places `stones` random stones close to the center so that deterministic engines don't replay the same game.
+pub fn random_opening<R: Rng + ?Sized>(stones: usize, rng: &mut R) -> Board {
+    let mut board = Board::new();
+    for _ in 0..stones {
+        let cp = board.free_positions()
+            .filter(|cp| center().x().abs_diff(cp.x()) <= 2 && center().y().abs_diff(cp.y()) <= 2)
+            .choose(rng)
+            .unwrap();
+        board.make_move(cp);
+    }
+    board
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use crate::ai::{BobAI, John};
+
+use super::*;
+
+/// Middle game positions, as the moves that lead to them.
+const POSITIONS: [&str; 6] = [
+    "h8 h9 i9 g7 j10 k11",
+    "h8 h9 i9 g7 j10 k11 i8 i7 j8 k8",
+    "h8 h9 i9 g7 j10 k11 i8 i7 j8 k8 g8 f8 j9 j7",
+    "h8 i9 g9 g7 h7 h6 i7 j7",
+    "h8 h7 i8 g8 i7 i9 j6 k5 g6",
+    "h8 j8 h9 h10 i9 g9 i10 i11",
+];
+
+fn position(moves: &str) -> Board {
+    let mut board = Board::new();
+    for name in moves.split_whitespace() {
+        board.make_move(name.parse().unwrap());
+    }
+    board
+}
+
+/// Nodes `BobAI` at `depth` and `John` at `compute` search in every position of the set,
+/// with `feature` turned off and on.
+fn compare(depth: u8, compute: f32, name: &str, feature: impl Fn(&mut BobAI, &mut John, bool)) {
+    println!("{:<48} {:>10} {:>10} {:>10} {:>10}", "position", "bob", name, "john", name);
+    let mut totals = [0; 4];
+    let mut times = [Duration::ZERO; 4];
+    for moves in POSITIONS {
+        let board = position(moves);
+        let mut nodes = [0; 4];
+        for (i, on) in [false, true].into_iter().enumerate() {
+            let mut bob = BobAI::new(depth);
+            let mut john = John::new(compute, 2., 4);
+            bob.debug = false;
+            john.debug = false;
+            feature(&mut bob, &mut john, on);
+
+            let start = Instant::now();
+            bob.next(&board);
+            times[i] += start.elapsed();
+            nodes[i] = bob.computed_positions;
+
+            let start = Instant::now();
+            john.next(&board);
+            times[i + 2] += start.elapsed();
+            nodes[i + 2] = john.nodes;
+        }
+        println!("{:<48} {:>10} {:>10} {:>10} {:>10}", moves, nodes[0], nodes[1], nodes[2], nodes[3]);
+        for (total, n) in totals.iter_mut().zip(nodes) {
+            *total += n;
+        }
+    }
+    println!("{:<48} {:>10} {:>10} {:>10} {:>10}", "total", totals[0], totals[1], totals[2], totals[3]);
+    let [a, b, c, d] = times.map(|time| format!("{:.2}s", time.as_secs_f32()));
+    println!("{:<48} {:>10} {:>10} {:>10} {:>10}", "time", a, b, c, d);
+}
+
+/// Unordered `BobAI` evaluates every child to sort them, which isn't counted as nodes, compare the times too.
+pub fn move_ordering(depth: u8, compute: f32) {
+    compare(depth, compute, "ordered", |bob, john, on| {
+        bob.move_ordering = on;
+        john.move_ordering = on;
+    });
+}
+
+/// Principal variation search and aspiration windows against full windows everywhere.
+pub fn narrow_windows(depth: u8, compute: f32) {
+    compare(depth, compute, "pvs", |bob, john, on| {
+        bob.narrow_windows = on;
+        john.narrow_windows = on;
+    });
+}
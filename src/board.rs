@@ -1,7 +1,10 @@
 use std::ops::{Neg, Mul};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use strum::Display;
 
+use crate::ai::{Accumulator, Network};
+
 use super::*;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -37,6 +40,27 @@ pub fn dir(i: usize) -> Dir {
     DIRS[i%8]
 }
 
+/// Largest board the hash table is allocated for.
+pub const MAX_BOARD_SIZE: usize = 19;
+
+static BOARD_SIZE: AtomicUsize = AtomicUsize::new(15);
+
+/// Side length of every board in this process.
+pub fn board_size() -> usize {
+    BOARD_SIZE.load(Ordering::Relaxed)
+}
+
+/// Has to be called before the first board is created, boards don't resize.
+pub fn set_board_size(size: usize) {
+    assert!((5..=MAX_BOARD_SIZE).contains(&size), "board size has to be between 5 and {}", MAX_BOARD_SIZE);
+    BOARD_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Where engines put the first stone.
+pub fn center() -> CellPos {
+    cell(board_size() / 2, board_size() / 2)
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
 pub enum Stone {
     Black,
@@ -63,24 +87,88 @@ impl Distribution<Stone> for Standard {
     }
 }
 
+/// Changing it changes every hash, along with anything stored by hash.
+const ZOBRIST_SEED: u64 = 0x4d59_5df4_d0f3_3173;
+
+struct Zobrist {
+    /// key of a stone of each colour on each cell, indexed by `x * MAX_BOARD_SIZE + y`
+    stones: [[u64; 2]; MAX_BOARD_SIZE*MAX_BOARD_SIZE],
+    /// hashed in while Black is to move
+    black_to_move: u64,
+}
+
+/// splitmix64, returns the next state and the number it gives
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+impl Zobrist {
+    const fn generate(seed: u64) -> Self {
+        let (mut state, black_to_move) = splitmix64(seed);
+        let mut stones = [[0; 2]; MAX_BOARD_SIZE*MAX_BOARD_SIZE];
+        let mut i = 0;
+        while i < stones.len() {
+            let (next, white) = splitmix64(state);
+            let (next, black) = splitmix64(next);
+            stones[i] = [black, white];
+            state = next;
+            i += 1;
+        }
+        Self { stones, black_to_move }
+    }
+
+    /// What placing or removing `stone` at `cp` does to the hash, the side to move changes as well.
+    fn toggle(&self, cp: CellPos, stone: Stone) -> u64 {
+        self.stones[cp.0*MAX_BOARD_SIZE+cp.1][stone as usize] ^ self.black_to_move
+    }
+}
+
+/// Worked out at compile time, shared by all boards.
+static ZOBRIST: Zobrist = Zobrist::generate(ZOBRIST_SEED);
+
 #[derive(Clone, Debug)]
 pub struct Board {
     data: Vec<Vec<Option<Stone>>>,
     pub turn: Stone,
+    /// Zobrist hash of the stones and the side to move, the same position hashes the same on every board
     pub hash: u64,
-    pub cell_hashes: [[u64; 3]; 15*15]
+    /// only present when an engine evaluates with a network
+    pub accumulator: Option<Accumulator>,
+    history: Vec<CellPos>,
+    /// how far along the eight lines from a stone an empty cell still counts as a candidate
+    radius: usize,
+    /// stones within `radius` of each cell, indexed by `x * board_size() + y`
+    near: Vec<u16>,
+    /// empty cells with a stone within `radius`, in no particular order
+    candidates: Vec<CellPos>,
+    /// where each cell is in `candidates`
+    candidate_index: Vec<Option<usize>>,
 }
 
+/// Default `Board` candidate radius, two cells along any line like the engines always looked.
+pub const CANDIDATE_RADIUS: usize = 2;
+
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 pub struct CellPos(usize, usize);
 
 impl CellPos {
+    pub fn x(self) -> usize {
+        self.0
+    }
+    pub fn y(self) -> usize {
+        self.1
+    }
     pub fn try_add(self, shift: Dir) -> Option<Self> {
         let x = self.0 as isize + shift.0;
         let y = self.1 as isize + shift.1;
 
-        if x < 0 || x >= 15 || y < 0 || y >= 15 {
+        let size = board_size() as isize;
+        if x < 0 || x >= size || y < 0 || y >= size {
             None
         } else {
             Some(cell(x as usize, y as usize))
@@ -89,13 +177,13 @@ impl CellPos {
 }
 
 pub fn cell(x: usize, y: usize) -> CellPos {
-    assert!(x < 15 && y < 15);
+    assert!(x < board_size() && y < board_size());
     CellPos(x, y)
 }
 
 impl Distribution<CellPos> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> CellPos {
-        CellPos(rng.gen_range(0..15), rng.gen_range(0..15))
+        CellPos(rng.gen_range(0..board_size()), rng.gen_range(0..board_size()))
     }
 }
 
@@ -109,29 +197,46 @@ impl std::ops::Index<CellPos> for Board {
 
 impl Board {
     pub fn new() -> Self {
-        let mut cell_hashes = [[0; 3]; 15*15];
-        let mut hash = 0;
-        for i in 0..15*15 {
-            for j in 0..3 {
-                cell_hashes[i][j] = random();
-            }
-            hash ^= cell_hashes[i][0];
-        }
+        let size = board_size();
         Self {
-            data: vec![vec![None; 15]; 15],
+            data: vec![vec![None; size]; size],
             turn: Stone::White,
-            hash,
-            cell_hashes
+            hash: 0,
+            accumulator: None,
+            history: vec![],
+            radius: CANDIDATE_RADIUS,
+            near: vec![0; size*size],
+            candidates: vec![],
+            candidate_index: vec![None; size*size],
+        }
+    }
+    /// Changes how far from the stones candidates reach, the counts are redone from scratch.
+    pub fn set_candidate_radius(&mut self, radius: usize) {
+        let stones = self.history.clone();
+        for &cp in stones.iter().rev() {
+            self.update_near(cp, false);
+        }
+        self.radius = radius;
+        for &cp in &stones {
+            self.update_near(cp, true);
         }
     }
+    pub fn attach_network(&mut self, network: Arc<Network>) {
+        self.accumulator = Some(Accumulator::new(network, self));
+    }
     pub fn make_move(&mut self, cp: CellPos) -> bool {
         if self[cp].is_some() {
             return false;
         }
         self.set(cp, Some(self.turn));
+        self.update_near(cp, true);
+
+        self.hash ^= ZOBRIST.toggle(cp, self.turn);
 
-        self.hash ^= self.cell_hashes[cp.0*15+cp.1][0];
-        self.hash ^= self.cell_hashes[cp.0*15+cp.1][self.turn as usize+1];
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.add(cp, self.turn);
+        }
+        self.history.push(cp);
 
         self.turn = -self.turn;
         true
@@ -141,15 +246,74 @@ impl Board {
 
         self.turn = -self.turn;
 
-        self.hash ^= self.cell_hashes[cp.0*15+cp.1][self.turn as usize+1];
-        self.hash ^= self.cell_hashes[cp.0*15+cp.1][0];
+        self.hash ^= ZOBRIST.toggle(cp, self.turn);
+
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.remove(cp, self.turn);
+        }
+        let last = self.history.pop();
+        debug_assert_eq!(last, Some(cp), "moves have to be unmade in reverse order");
 
         self.set(cp, None);
+        self.update_near(cp, false);
+    }
+
+    /// Moves played on this board, oldest first.
+    pub fn history(&self) -> &[CellPos] {
+        &self.history
+    }
+    pub fn last_move(&self) -> Option<CellPos> {
+        self.history.last().copied()
     }
 
     fn set(&mut self, cp: CellPos, stone: Option<Stone>) {
         self.data[cp.0][cp.1] = stone;
     }
+    /// Empty cells within the candidate radius of a stone, the moves worth searching.
+    pub fn candidates(&self) -> impl Iterator<Item = CellPos> + '_ {
+        self.candidates.iter().copied()
+    }
+    pub fn is_candidate(&self, cp: CellPos) -> bool {
+        self.candidate_index[cp.0*board_size()+cp.1].is_some()
+    }
+
+    /// Keeps `near` and `candidates` right after the stone at `cp` was placed (or before it's removed).
+    fn update_near(&mut self, cp: CellPos, placed: bool) {
+        let size = board_size();
+        self.set_candidate(cp, self[cp].is_none() && self.near[cp.0*size+cp.1] > 0);
+        for i in 0..8 {
+            for k in 1..=self.radius {
+                let Some(next) = cp.try_add(k * dir(i)) else {
+                    break;
+                };
+                let near = &mut self.near[next.0*size+next.1];
+                if placed {
+                    *near += 1;
+                } else {
+                    *near -= 1;
+                }
+                let candidate = *near > 0 && self[next].is_none();
+                self.set_candidate(next, candidate);
+            }
+        }
+    }
+    fn set_candidate(&mut self, cp: CellPos, candidate: bool) {
+        let i = cp.0*board_size()+cp.1;
+        match (self.candidate_index[i], candidate) {
+            (None, true) => {
+                self.candidate_index[i] = Some(self.candidates.len());
+                self.candidates.push(cp);
+            }
+            (Some(index), false) => {
+                self.candidates.swap_remove(index);
+                if let Some(&moved) = self.candidates.get(index) {
+                    self.candidate_index[moved.0*board_size()+moved.1] = Some(index);
+                }
+                self.candidate_index[i] = None;
+            }
+            _ => {}
+        }
+    }
     pub fn free_positions(&self) -> FreePosIterator {
         FreePosIterator {
             board: self,
@@ -157,9 +321,13 @@ impl Board {
         }
     }
 
+    pub fn is_full(&self) -> bool {
+        self.free_positions().next().is_none()
+    }
+
     pub fn check_win(&self) -> bool {
-        for x in 0..15 {
-            for y in 0..15 {
+        for x in 0..board_size() {
+            for y in 0..board_size() {
                 if self.check_win_from(cell(x, y)) {
                     return true;
                 }
@@ -183,6 +351,16 @@ impl Board {
         false
     }
     
+    /// The five (or longer) line the stone at `cp` is part of.
+    pub fn winning_line(&self, cp: CellPos) -> Option<Vec<CellPos>> {
+        let dir_lengths = self.compute_dir_lengths_from(cp);
+        let i = (0..4).find(|&i| self[cp].is_some() && dir_lengths[i] + dir_lengths[i + 4] > 5)?;
+        let start = cp.try_add((dir_lengths[i + 4] - 1) * dir(i + 4)).unwrap();
+        Some((0..dir_lengths[i] + dir_lengths[i + 4] - 1).map(|k| start.try_add(k * dir(i)).unwrap()).collect())
+    }
+    /// Stones in a row from `cp` in each direction, `cp` included and capped at 6.
+    /// For an empty `cp` the first step is taken whatever is there and the run follows that cell
+    /// (empty ones included), `patterns::shapes` is the cleaner way to look at lines.
     pub fn compute_dir_lengths_from(&self, cp: CellPos) -> [usize; 8] {
         let mut result = [0; 8];
 
@@ -207,6 +385,12 @@ impl Board {
     }
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct FreePosIterator<'a> {
     board: &'a Board,
     i: usize,
@@ -216,9 +400,10 @@ impl Iterator for FreePosIterator<'_> {
     type Item = CellPos;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.i < 15 * 15 {
-            let x = self.i % 15;
-            let y = self.i / 15;
+        let size = self.board.data.len();
+        while self.i < size * size {
+            let x = self.i % size;
+            let y = self.i / size;
             self.i += 1;
             if self.board[cell(x, y)].is_none() {
                 return Some(cell(x, y));
@@ -228,6 +413,24 @@ impl Iterator for FreePosIterator<'_> {
     }
 }
 
+/// White's and Black's stones played in turn, White to move once both are on the board.
+/// The side with fewer stones is topped up with ones far enough apart to not matter.
+#[cfg(test)]
+pub(crate) fn board_with(white: &[CellPos], black: &[CellPos]) -> Board {
+    const WHITE_FILL: [(usize, usize); 6] = [(0, 0), (6, 0), (12, 0), (0, 14), (6, 14), (12, 14)];
+    const BLACK_FILL: [(usize, usize); 6] = [(14, 3), (14, 9), (3, 12), (9, 12), (2, 2), (14, 14)];
+    let count = white.len().max(black.len());
+    let fill = |stones: &[CellPos], fill: &[(usize, usize)]| {
+        stones.iter().copied().chain(fill.iter().map(|&(x, y)| cell(x, y))).take(count).collect::<Vec<_>>()
+    };
+    let mut board = Board::new();
+    for (w, b) in fill(white, &WHITE_FILL).into_iter().zip(fill(black, &BLACK_FILL)) {
+        board.make_move(w);
+        board.make_move(b);
+    }
+    board
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,18 +441,54 @@ mod tests {
         board.unmake_move(cell(1, 0));
         board.make_move(cell(1, 0));
         board.make_move(cell(2, 5));
+        board.make_move(cell(7, 7));
 
-        let mut hash = 0;
-
-        for i in 0..15*15 {
-            if let Some(stone) = board[cell(i/15, i%15)] {
-                hash ^= board.cell_hashes[i][stone as usize+1];
-            }
-            else {
-                hash ^= board.cell_hashes[i][0];
+        let mut hash = ZOBRIST.black_to_move;
+        for x in 0..board_size() {
+            for y in 0..board_size() {
+                if let Some(stone) = board[cell(x, y)] {
+                    hash ^= ZOBRIST.stones[x*MAX_BOARD_SIZE+y][stone as usize];
+                }
             }
         }
-
         assert_eq!(hash, board.hash);
+
+        // another board reaching the same position by another order
+        let mut other = Board::new();
+        for cp in [cell(7, 7), cell(2, 5), cell(1, 0)] {
+            other.make_move(cp);
+        }
+        assert_eq!(other.hash, board.hash);
+        other.unmake_move(cell(1, 0));
+        assert_ne!(other.hash, board.hash);
+    }
+
+    #[test]
+    fn test_candidates() {
+        // what the engines used to check for every cell
+        let near_stone = |board: &Board, cp: CellPos, radius: usize| {
+            board[cp].is_none() && (0..8).any(|i| (1..=radius).any(|k| cp.try_add(k * dir(i)).is_some_and(|next| board[next].is_some())))
+        };
+        let check = |board: &Board, radius: usize| {
+            let mut candidates = board.candidates().collect::<Vec<_>>();
+            candidates.sort();
+            let mut expected = board.free_positions().filter(|cp| near_stone(board, *cp, radius)).collect::<Vec<_>>();
+            expected.sort();
+            assert_eq!(candidates, expected);
+        };
+
+        let mut board = Board::new();
+        let moves = [cell(7, 7), cell(7, 8), cell(0, 0), cell(9, 9), cell(8, 8), cell(14, 13)];
+        for &cp in &moves {
+            board.make_move(cp);
+            check(&board, CANDIDATE_RADIUS);
+        }
+        board.set_candidate_radius(1);
+        check(&board, 1);
+        for &cp in moves.iter().rev() {
+            board.unmake_move(cp);
+            check(&board, 1);
+        }
+        assert_eq!(board.candidates().count(), 0);
     }
 }
\ No newline at end of file
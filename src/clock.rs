@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeControl {
+    /// `base` for the whole game, `increment` added after every move
+    Fischer { base: Duration, increment: Duration },
+    /// every move gets the same time, nothing carries over
+    PerMove(Duration),
+    /// `base` for the whole game and that's it
+    SuddenDeath(Duration),
+}
+
+impl TimeControl {
+    fn base(&self) -> Duration {
+        match *self {
+            Self::Fischer { base, .. } => base,
+            Self::PerMove(per_move) => per_move,
+            Self::SuddenDeath(base) => base,
+        }
+    }
+    fn increment(&self) -> Duration {
+        match *self {
+            Self::Fischer { increment, .. } => increment,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// What the actor to move is told about its time.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeBudget {
+    pub control: TimeControl,
+    pub remaining: Duration,
+}
+
+impl TimeBudget {
+    /// How long an engine should think about this move, leaves some slack for the overhead around the search.
+    pub fn target(&self) -> Duration {
+        match self.control {
+            TimeControl::PerMove(_) => self.remaining.mul_f32(0.8),
+            TimeControl::Fischer { increment, .. } => {
+                (self.remaining / 30 + increment.mul_f32(0.8)).min(self.remaining / 2)
+            }
+            TimeControl::SuddenDeath(_) => self.remaining / 40,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Clock {
+    pub control: TimeControl,
+    /// indexed by `Stone as usize`
+    remaining: [Duration; 2],
+    running: Option<(Stone, Instant)>,
+    /// nobody's time goes down meanwhile, e.g. while a replay is shown over the game
+    paused: bool,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            remaining: [control.base(); 2],
+            running: None,
+            paused: false,
+        }
+    }
+
+    /// Time the running side used since its clock was started or resumed.
+    fn elapsed(&self, start: Instant) -> Duration {
+        if self.paused { Duration::ZERO } else { start.elapsed() }
+    }
+
+    /// Time left for `stone` including the move being thought about right now.
+    pub fn remaining(&self, stone: Stone) -> Duration {
+        let remaining = self.remaining[stone as usize];
+        match self.running {
+            Some((running, start)) if running == stone => remaining.saturating_sub(self.elapsed(start)),
+            _ => remaining,
+        }
+    }
+
+    pub fn budget(&self, stone: Stone) -> TimeBudget {
+        TimeBudget { control: self.control, remaining: self.remaining(stone) }
+    }
+
+    pub fn running(&self) -> Option<Stone> {
+        self.running.map(|(stone, _)| stone)
+    }
+
+    pub fn start(&mut self, stone: Stone) {
+        self.running = Some((stone, Instant::now()));
+    }
+
+    /// Stops the running clock, returns false if its side ran out of time.
+    pub fn stop(&mut self) -> bool {
+        let Some((stone, start)) = self.running.take() else {
+            return true;
+        };
+        let elapsed = self.elapsed(start);
+        let remaining = &mut self.remaining[stone as usize];
+        match remaining.checked_sub(elapsed) {
+            Some(left) => {
+                *remaining = left;
+                true
+            }
+            None => {
+                *remaining = Duration::ZERO;
+                false
+            }
+        }
+    }
+
+    /// Charges the running side for its time so far and freezes both clocks until `resume`.
+    pub fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+        if let Some((stone, start)) = self.running {
+            self.remaining[stone as usize] = self.remaining[stone as usize].saturating_sub(start.elapsed());
+        }
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+        self.paused = false;
+        if let Some((stone, _)) = self.running {
+            self.running = Some((stone, Instant::now()));
+        }
+    }
+
+    /// Called once `stone` actually made a move (illegal attempts don't count).
+    pub fn complete_move(&mut self, stone: Stone) {
+        let remaining = &mut self.remaining[stone as usize];
+        *remaining = match self.control {
+            TimeControl::PerMove(per_move) => per_move,
+            _ => *remaining + self.control.increment(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increment() {
+        let mut clock = Clock::new(TimeControl::Fischer { base: Duration::from_secs(60), increment: Duration::from_secs(5) });
+        clock.start(Stone::White);
+        assert_eq!(clock.running(), Some(Stone::White));
+        assert!(clock.stop());
+        clock.complete_move(Stone::White);
+        let white = clock.remaining(Stone::White);
+        assert!(white > Duration::from_secs(64) && white <= Duration::from_secs(65));
+        assert_eq!(clock.remaining(Stone::Black), Duration::from_secs(60));
+
+        let mut clock = Clock::new(TimeControl::PerMove(Duration::from_secs(10)));
+        clock.start(Stone::White);
+        clock.stop();
+        clock.complete_move(Stone::White);
+        assert_eq!(clock.remaining(Stone::White), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_flag_fall() {
+        let mut clock = Clock::new(TimeControl::SuddenDeath(Duration::from_millis(10)));
+        clock.start(Stone::Black);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(clock.remaining(Stone::Black), Duration::ZERO);
+        assert!(!clock.stop());
+        assert_eq!(clock.running(), None);
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut clock = Clock::new(TimeControl::SuddenDeath(Duration::from_millis(100)));
+        clock.start(Stone::White);
+        clock.pause();
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(clock.remaining(Stone::White) > Duration::from_millis(50));
+        // a move finished or started while paused costs nothing either
+        assert!(clock.stop());
+        clock.start(Stone::Black);
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(clock.remaining(Stone::Black), Duration::from_millis(100));
+
+        clock.resume();
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!clock.stop());
+    }
+
+    #[test]
+    fn test_budget() {
+        let fischer = TimeControl::Fischer { base: Duration::from_secs(300), increment: Duration::from_secs(5) };
+        let budget = Clock::new(fischer).budget(Stone::White);
+        assert_eq!(budget.remaining, Duration::from_secs(300));
+        assert_eq!(budget.target(), Duration::from_secs(14));
+
+        // never more than half of what's left
+        let budget = TimeBudget { control: fischer, remaining: Duration::from_secs(6) };
+        assert_eq!(budget.target(), Duration::from_secs(3));
+
+        let budget = Clock::new(TimeControl::SuddenDeath(Duration::from_secs(400))).budget(Stone::Black);
+        assert_eq!(budget.target(), Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::ai::BoardState;
+
+use super::*;
+
+/// Something the player should know about the move they just made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// playing at the cell would have won on the spot
+    MissedWin(CellPos),
+    /// the opponent completes five at the cell
+    AllowsFive(CellPos),
+    /// the opponent gets an open four at the cell
+    AllowsOpenFour(CellPos),
+    /// the opponent gets a four-three or a double four at the cell
+    AllowsDoubleThreat(CellPos),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::MissedWin(cp) => write!(f, "you could have won at {}", cp),
+            Self::AllowsFive(cp) => write!(f, "the opponent wins at {} now", cp),
+            Self::AllowsOpenFour(cp) => write!(f, "the opponent gets an open four at {}", cp),
+            Self::AllowsDoubleThreat(cp) => write!(f, "the opponent gets a double threat at {}", cp),
+        }
+    }
+}
+
+/// Checks `mv` played on `before`. A position that was lost already isn't blamed on the move.
+pub fn review(before: &Board, mv: CellPos) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut after = before.clone();
+    after.make_move(mv);
+    if after.check_win_from(mv) {
+        return warnings;
+    }
+
+    let lost = match BoardState::compute(before) {
+        BoardState::OneMoveWin(cp) => {
+            warnings.push(Warning::MissedWin(cp));
+            false
+        }
+        BoardState::OneMoveLoss(_) => true,
+        BoardState::DefendOrCounterFour(mvs) => mvs.is_empty(),
+        _ => false,
+    };
+    if !lost {
+        match BoardState::compute(&after) {
+            BoardState::OneMoveWin(cp) => warnings.push(Warning::AllowsFive(cp)),
+            BoardState::TwoMoveWin(cp) => warnings.push(Warning::AllowsOpenFour(cp)),
+            BoardState::FourThree(cp) | BoardState::DoubleFour(cp) => warnings.push(Warning::AllowsDoubleThreat(cp)),
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// `review` of `mv` found in `board`'s history, the opponent may have answered it already.
+pub fn review_played(board: &Board, mv: CellPos) -> Vec<Warning> {
+    let mut before = board.clone();
+    let Some(played) = before.history().iter().rposition(|cp| *cp == mv) else {
+        return vec![];
+    };
+    while before.history().len() > played {
+        before.unmake_move(before.last_move().unwrap());
+    }
+    review(&before, mv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missed_win() {
+        let mut board = Board::new();
+        for x in 3..7 {
+            board.make_move(cell(x, 7));
+            board.make_move(cell(x, 2));
+        }
+        let warnings = review(&board, cell(12, 12));
+        assert!(matches!(warnings[..], [Warning::MissedWin(_), Warning::AllowsFive(_)]), "{:?}", warnings);
+        assert!(review(&board, cell(7, 7)).is_empty());
+    }
+}
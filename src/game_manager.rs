@@ -1,40 +1,340 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::ai::{BoardState, bobs_shallow_eval};
+use crate::rules::{Rules, IllegalMove, IllegalMovePolicy};
+use crate::clock::{Clock, TimeControl};
 
 use super::*;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    BlackWin,
+    WhiteWin,
+    /// board filled up or both sides agreed
+    Draw,
+    Forfeit { loser: Stone, reason: String },
+    Resignation(Stone),
+    /// stopped from the outside before it ended
+    Aborted,
+}
+
+impl GameResult {
+    pub fn win(stone: Stone) -> Self {
+        match stone {
+            Stone::Black => Self::BlackWin,
+            Stone::White => Self::WhiteWin,
+        }
+    }
+    pub fn winner(&self) -> Option<Stone> {
+        match self {
+            Self::BlackWin => Some(Stone::Black),
+            Self::WhiteWin => Some(Stone::White),
+            Self::Draw => None,
+            Self::Forfeit { loser, .. } => Some(-*loser),
+            Self::Resignation(loser) => Some(-*loser),
+            Self::Aborted => None,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlackWin => write!(f, "Black wins!"),
+            Self::WhiteWin => write!(f, "White wins!"),
+            Self::Draw => write!(f, "Draw."),
+            Self::Forfeit { loser, reason } => write!(f, "{} forfeits ({}), {} wins!", loser, reason, -*loser),
+            Self::Resignation(loser) => write!(f, "{} resigns, {} wins!", loser, -*loser),
+            Self::Aborted => write!(f, "Game stopped."),
+        }
+    }
+}
+
+/// What subscribers of a running `GameManager` get told about.
+#[derive(Clone, Debug)]
+pub enum GameEvent {
+    Moved { cp: CellPos, stone: Stone },
+    /// `stone` wants to take back the last two moves, its opponent is asked now
+    UndoRequested { stone: Stone },
+    /// the last two moves were taken back
+    Undone,
+    /// the move `stone` returned broke the rules and wasn't played, `stone` is asked again unless it forfeits
+    Rejected { stone: Stone, illegal_move: IllegalMove },
+    Over(GameResult),
+}
+
+pub(crate) enum Turn {
+    Move(CellPos),
+    Undo,
+    /// nothing happened, the same side is asked again
+    Again,
+    Over(GameResult),
+}
+
+/// Asks the actor to move what it wants to do, the game is over without a move on a full board,
+/// resignation, forfeit or an accepted draw offer.
+pub(crate) fn take_turn(board: &Board, actor: &mut dyn Actor, opponent: &mut dyn Actor) -> Turn {
+    if board.is_full() {
+        return Turn::Over(GameResult::Draw);
+    }
+    if actor.resign(board) {
+        return Turn::Over(GameResult::Resignation(board.turn));
+    }
+    if actor.offer_draw(board) && opponent.accept_draw(board) {
+        return Turn::Over(GameResult::Draw);
+    }
+    match actor.act(board) {
+        Action::Move(cp) => Turn::Move(cp),
+        Action::Resign => Turn::Over(GameResult::Resignation(board.turn)),
+        Action::RequestUndo => Turn::Undo,
+        // an answer nobody waits for anymore
+        Action::TimedOut | Action::AnswerUndo(_) => Turn::Again,
+        Action::Forfeit(reason) => Turn::Over(GameResult::Forfeit { loser: board.turn, reason }),
+    }
+}
+
 pub struct GameManager {
     board: Arc<RwLock<Board>>,
+    result: Arc<RwLock<Option<GameResult>>>,
+    clock: Arc<RwLock<Option<Clock>>>,
     black_actor: Box<dyn Actor>,
     white_actor: Box<dyn Actor>,
+    pub rules: Rules,
+    /// indexed by `Stone as usize`
+    policies: [IllegalMovePolicy; 2],
+    illegal_moves: [usize; 2],
+    subscribers: Vec<mpsc::Sender<GameEvent>>,
+    stop: Arc<AtomicBool>,
+    /// print every move with its evaluation
+    pub debug: bool,
 }
 
 impl GameManager {
     pub fn new(board: Arc<RwLock<Board>>, black_actor: Box<dyn Actor>, white_actor: Box<dyn Actor>) -> Self {
         Self {
+            policies: [black_actor.illegal_move_policy(), white_actor.illegal_move_policy()],
             black_actor,
             white_actor,
             board,
+            result: Default::default(),
+            clock: Default::default(),
+            rules: Rules::default(),
+            illegal_moves: [0; 2],
+            subscribers: vec![],
+            stop: Default::default(),
+            debug: true,
         }
     }
-    pub fn run(mut self) {
-        loop {
+    pub fn subscribe(&mut self) -> mpsc::Receiver<GameEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+    fn notify(subscribers: &mut Vec<mpsc::Sender<GameEvent>>, event: GameEvent) {
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+    /// Overrides the policy the actor of `stone` asked for with `Actor::illegal_move_policy`.
+    pub fn set_policy(&mut self, stone: Stone, policy: IllegalMovePolicy) {
+        self.policies[stone as usize] = policy;
+    }
+    /// Filled in once `run` finishes, so that other threads can show it.
+    pub fn result(&self) -> Arc<RwLock<Option<GameResult>>> {
+        self.result.clone()
+    }
+    pub fn set_time_control(&mut self, time_control: TimeControl) {
+        *self.clock.write().unwrap() = Some(Clock::new(time_control));
+    }
+    pub fn clock(&self) -> Arc<RwLock<Option<Clock>>> {
+        self.clock.clone()
+    }
+    /// Setting it ends the game as `Aborted` once the actor to move returns, nothing is played after that.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    fn actor_mut(&mut self, stone: Stone) -> &mut dyn Actor {
+        match stone {
+            Stone::Black => self.black_actor.as_mut(),
+            Stone::White => self.white_actor.as_mut(),
+        }
+    }
+    pub fn run(mut self) -> GameResult {
+        self.black_actor.set_rules(self.rules);
+        self.white_actor.set_rules(self.rules);
+        self.black_actor.set_stop(self.stop.clone());
+        self.white_actor.set_stop(self.stop.clone());
+        let result = loop {
             std::thread::sleep(std::time::Duration::from_millis(100));
+            if self.stopped() {
+                break GameResult::Aborted;
+            }
+            let stone = self.board.read().unwrap().turn;
+
+            let budget = self.clock.read().unwrap().as_ref().map(|clock| clock.budget(stone));
+            if let Some(budget) = budget {
+                self.actor_mut(stone).set_time_budget(budget);
+            }
+            if let Some(clock) = self.clock.write().unwrap().as_mut() {
+                clock.start(stone);
+            }
             let board = self.board.read().unwrap();
-            let next_move = match board.turn {
-                Stone::Black => self.black_actor.next(&board),
-                Stone::White => self.white_actor.next(&board),
+            let turn = match stone {
+                Stone::Black => take_turn(&board, self.black_actor.as_mut(), self.white_actor.as_mut()),
+                Stone::White => take_turn(&board, self.white_actor.as_mut(), self.black_actor.as_mut()),
             };
             drop(board);
+            if self.stopped() {
+                break GameResult::Aborted;
+            }
+            if let Some(clock) = self.clock.write().unwrap().as_mut() {
+                if !clock.stop() {
+                    break GameResult::Forfeit { loser: stone, reason: "lost on time".to_string() };
+                }
+            }
+            let next_move = match turn {
+                Turn::Move(next_move) => next_move,
+                Turn::Undo => {
+                    self.undo(stone);
+                    continue;
+                }
+                Turn::Again => continue,
+                Turn::Over(result) => break result,
+            };
             let mut board = self.board.write().unwrap();
-            if board.make_move(next_move) {
+            if let Err(illegal_move) = self.rules.validate(&board, next_move) {
+                self.illegal_moves[stone as usize] += 1;
+                let attempts = self.illegal_moves[stone as usize];
+                if self.debug {
+                    println!("ILLEGAL MOVE by {}: {} (attempt {})", stone, illegal_move, attempts);
+                }
+                match self.policies[stone as usize] {
+                    IllegalMovePolicy::Forfeit(allowed) if attempts >= allowed => {
+                        break GameResult::Forfeit {
+                            loser: stone,
+                            reason: format!("illegal move, {} ({} attempts)", illegal_move, attempts)
+                        };
+                    }
+                    _ => {
+                        Self::notify(&mut self.subscribers, GameEvent::Rejected { stone, illegal_move });
+                        continue;
+                    }
+                }
+            }
+            board.make_move(next_move);
+            Self::notify(&mut self.subscribers, GameEvent::Moved { cp: next_move, stone });
+            if let Some(clock) = self.clock.write().unwrap().as_mut() {
+                clock.complete_move(stone);
+            }
+            if self.debug {
                 println!("TURN: {:?}", board.turn);
                 println!("{:?}", BoardState::compute(&board));
                 println!("{}", bobs_shallow_eval(&board, true));
-                if board.check_win_from(next_move) {
-                    println!("{} wins!", -board.turn);
-                    break;
-                }
             }
+            if self.rules.is_win(&board, next_move) {
+                break GameResult::win(-board.turn);
+            }
+        };
+        if self.debug {
+            println!("{}", result);
         }
+        let board = self.board.read().unwrap();
+        self.black_actor.game_over(&board, &result);
+        self.white_actor.game_over(&board, &result);
+        drop(board);
+        *self.result.write().unwrap() = Some(result.clone());
+        Self::notify(&mut self.subscribers, GameEvent::Over(result.clone()));
+        result
     }
-}
\ No newline at end of file
+
+    /// `stone` asked to take back the opponent's last move and its own one before it.
+    fn undo(&mut self, stone: Stone) {
+        // the opponent may take a while to answer, don't keep the board locked meanwhile
+        let board = self.board.read().unwrap();
+        if board.history().len() >= 2 {
+            Self::notify(&mut self.subscribers, GameEvent::UndoRequested { stone });
+        }
+        let accepted = board.history().len() >= 2 && match stone {
+            Stone::Black => self.white_actor.accept_undo(&board),
+            Stone::White => self.black_actor.accept_undo(&board),
+        };
+        drop(board);
+        let mut board = self.board.write().unwrap();
+        if accepted {
+            for _ in 0..2 {
+                let last = board.last_move().unwrap();
+                board.unmake_move(last);
+            }
+        }
+        if self.debug {
+            println!("UNDO requested by {}: {}", stone, if accepted { "accepted" } else { "declined" });
+        }
+        match stone {
+            Stone::Black => self.black_actor.undo_answered(&board, accepted),
+            Stone::White => self.white_actor.undo_answered(&board, accepted),
+        }
+        if accepted {
+            Self::notify(&mut self.subscribers, GameEvent::Undone);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays the same cell over and over.
+    struct Stubborn(CellPos);
+
+    impl Actor for Stubborn {
+        fn next(&mut self, _: &Board) -> CellPos {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_forfeit_after_attempts() {
+        // White takes the centre first, Black keeps playing on it
+        let board = Arc::new(RwLock::new(Board::new()));
+        let mut game_manager = GameManager::new(board, Box::new(Stubborn(center())), Box::new(Stubborn(center())));
+        game_manager.debug = false;
+        game_manager.set_policy(Stone::Black, IllegalMovePolicy::Forfeit(3));
+        let events = game_manager.subscribe();
+
+        let result = game_manager.run();
+        assert!(matches!(&result, GameResult::Forfeit { loser: Stone::Black, reason } if reason.contains("3 attempts")));
+        let rejected = events.try_iter().filter(|event| matches!(event, GameEvent::Rejected { stone: Stone::Black, .. })).count();
+        assert_eq!(rejected, 2);
+    }
+
+    #[test]
+    fn test_stop_cuts_search_short() {
+        // John would think about its second move for ages
+        let board = Arc::new(RwLock::new(Board::new()));
+        let mut john = crate::ai::John::new(1e12, 2., 4);
+        john.debug = false;
+        let mut game_manager = GameManager::new(board, Box::new(john), Box::new(Stubborn(center())));
+        game_manager.debug = false;
+        let stop = game_manager.stop_handle();
+        let start = std::time::Instant::now();
+        let game = std::thread::spawn(move || game_manager.run());
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(game.join().unwrap(), GameResult::Aborted);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_human_flags() {
+        // the human never answers, the clock has to end the game anyway
+        let (_actions, receiver) = mpsc::channel();
+        let board = Arc::new(RwLock::new(Board::new()));
+        let mut game_manager = GameManager::new(board, Box::new(Stubborn(center())), Box::new(actor::Player::new(receiver)));
+        game_manager.debug = false;
+        game_manager.set_time_control(TimeControl::SuddenDeath(std::time::Duration::from_millis(200)));
+        let result = game_manager.run();
+        assert!(matches!(&result, GameResult::Forfeit { loser: Stone::White, reason } if reason == "lost on time"));
+    }
+}
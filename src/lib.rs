@@ -2,56 +2,545 @@
 #![feature(drain_filter)]
 
 mod actor;
-mod board;
+pub mod board;
 mod game_manager;
 mod ai;
 mod textures;
+pub mod rules;
+mod arena;
+pub mod clock;
+pub mod tuner;
+pub mod selfplay;
+pub mod network;
+pub mod server;
+pub mod tui;
+pub mod replay;
+mod coach;
+pub mod review;
+pub mod patterns;
+pub mod bench;
+pub mod notation;
 
-use std::sync::{Arc, RwLock, mpsc};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use ai::John;
 use ellipsoid::prelude::*;
 
-use ellipsoid::prelude::winit::event::ElementState;
-use rand::{Rng, random};
+use ellipsoid::prelude::winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+use rand::Rng;
 use rand::distributions::{Distribution, Standard};
 
-use actor::Actor;
-use board::{Board, Stone, CellPos, cell, dir};
-use game_manager::GameManager;
+use actor::{Actor, Action};
+use board::{Board, Stone, CellPos, cell, dir, board_size, center};
+use game_manager::{GameEvent, GameManager, GameResult};
+use clock::{Clock, TimeControl};
+use network::{Chat, Connection, RemotePlayer};
+use rules::{IllegalMovePolicy, Rules};
 use textures::Txts;
+use replay::{Analyser, Replay};
+
+pub use ai::EngineConfig;
+
+/// `ellipsoid` constructs `Gomoku` itself, so a network game is handed over through here.
+static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+static SETUP: Mutex<Option<Setup>> = Mutex::new(None);
+static REPLAY: Mutex<Option<Replay>> = Mutex::new(None);
+
+/// Who plays a side of a local game.
+#[derive(Clone, Debug)]
+pub enum Side {
+    Human,
+    Engine(EngineConfig),
+}
+
+/// How a local game starts, the board size is set globally with `board::set_board_size`.
+#[derive(Clone, Debug)]
+pub struct Setup {
+    pub black: Side,
+    pub white: Side,
+    pub rules: Rules,
+    /// what both sides get for illegal moves, each actor picks its own with `Actor::illegal_move_policy` if unset
+    pub illegal_moves: Option<IllegalMovePolicy>,
+    /// games are untimed unless set
+    pub time_control: Option<TimeControl>,
+    /// moves played before the game starts, White first
+    pub position: Vec<CellPos>,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self {
+            black: Side::Engine(EngineConfig::new("john")),
+            white: Side::Human,
+            rules: Rules::default(),
+            illegal_moves: None,
+            time_control: None,
+            position: vec![],
+        }
+    }
+}
+
+impl Setup {
+    /// Reads moves like `h8 h9 i9`, White moves first. The game has to be still going after them.
+    pub fn load_position(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut board = Board::new();
+        let mut position = vec![];
+        for cp in replay::read_moves(path)? {
+            self.rules.validate(&board, cp).map_err(|e| invalid(e.to_string()))?;
+            board.make_move(cp);
+            if self.rules.is_win(&board, cp) {
+                return Err(invalid(format!("{} already wins the game", cp)));
+            }
+            position.push(cp);
+        }
+        self.position = position;
+        Ok(())
+    }
+}
+
+/// One game on its own `GameManager` thread.
+struct Game {
+    board: Arc<RwLock<Board>>,
+    result: Arc<RwLock<Option<GameResult>>>,
+    clock: Arc<RwLock<Option<Clock>>>,
+    /// moves of the human sides go through here, indexed by `Stone as usize`
+    humans: [Option<mpsc::Sender<Action>>; 2],
+    stop: Arc<AtomicBool>,
+    events: mpsc::Receiver<GameEvent>,
+}
+
+impl Game {
+    /// Starts the game described by `setup`, or the network game on `connection` if there is one.
+    fn new(setup: &Setup, connection: Option<Connection>) -> Self {
+        let mut board = Board::new();
+        if connection.is_none() {
+            for cp in &setup.position {
+                board.make_move(*cp);
+            }
+        }
+        let board = Arc::new(RwLock::new(board));
+
+        let mut humans = [None, None];
+        let mut actor = |side: &Side, stone: Stone| -> Box<dyn Actor> {
+            match side {
+                Side::Human => {
+                    let (transmitter, receiver) = mpsc::channel();
+                    humans[stone as usize] = Some(transmitter);
+                    Box::new(actor::Player::new(receiver))
+                }
+                Side::Engine(config) => config.build(true),
+            }
+        };
+        let networked = connection.is_some();
+        let (b_actor, w_actor) = match connection {
+            Some(connection) => {
+                let local = connection.local_stone();
+                let remote: Box<dyn Actor> = Box::new(RemotePlayer::new(connection));
+                match local {
+                    Stone::Black => (actor(&Side::Human, Stone::Black), remote),
+                    Stone::White => (remote, actor(&Side::Human, Stone::White)),
+                }
+            }
+            None => (actor(&setup.black, Stone::Black), actor(&setup.white, Stone::White)),
+        };
+
+        let mut game_manager = GameManager::new(board.clone(), b_actor, w_actor);
+        game_manager.rules = setup.rules;
+        if let Some(policy) = setup.illegal_moves {
+            for stone in [Stone::Black, Stone::White] {
+                game_manager.set_policy(stone, policy);
+            }
+        }
+        // both sides would run their own clock over the network, they'd never agree
+        if let Some(time_control) = setup.time_control.filter(|_| !networked) {
+            game_manager.set_time_control(time_control);
+        }
+        let result = game_manager.result();
+        let clock = game_manager.clock();
+        let stop = game_manager.stop_handle();
+        let events = game_manager.subscribe();
+        std::thread::spawn(|| {
+            game_manager.run()
+        });
+
+        Self { board, result, clock, humans, stop, events }
+    }
+}
+
+impl Drop for Game {
+    /// Human sides see their channel closed and the thread ends once the side to move returns,
+    /// an engine cuts its search short and its move isn't played anymore.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
 
 struct Gomoku {
     graphics: Graphics<Txts>,
-    board: Arc<RwLock<Board>>,
-    game_manager_thread: std::thread::JoinHandle<()>,
-    player_move_transmitter: mpsc::Sender<CellPos>,
-    player_stone: Option<Stone>,
+    game: Game,
+    /// what the next game started from the window looks like
+    setup: Setup,
     mouse_pos: Vec2,
+    /// shown instead of the game while set
+    replay: Option<Replay>,
+    replay_path: String,
+    replay_error: Option<String>,
+    /// present while the replay is analysed
+    analyser: Option<Analyser>,
+    /// last ply handed to the analyser
+    requested_ply: Option<usize>,
+    /// where the finished game went
+    saved: Option<String>,
+    /// numbers on the stones instead of the last-move marker, toggled with M
+    show_move_numbers: bool,
+    /// bounded `John` search for the human to move, asked for with H
+    hinter: Option<Analyser>,
+    /// warn about the human's moves, toggled with C
+    coach: bool,
+    coach_warnings: Vec<coach::Warning>,
+    /// why the last move of a human wasn't played, until the next move is
+    rejected: Option<String>,
+    /// the side asking to take back moves, until the human it asks answers
+    undo_request: Option<Stone>,
+    chat: Option<Chat>,
+    chat_input: String,
 }
 
-impl App<Txts> for Gomoku {
-    async fn new(window: winit::window::Window) -> Self {
-        let board = Arc::new(RwLock::new(Board::new()));
+impl Gomoku {
+    fn new_game(&mut self) {
+        self.game = Game::new(&self.setup, None);
+        self.hinter = None;
+        self.coach_warnings.clear();
+        self.rejected = None;
+        self.undo_request = None;
+        self.saved = None;
+    }
+
+    fn hint(&mut self) {
+        let board = self.game.board.read().unwrap();
+        if self.game.humans[board.turn as usize].is_none() {
+            return;
+        }
+        self.hinter.get_or_insert_with(Analyser::new).request(&board);
+    }
+
+    /// Reviews the human moves played since the last frame and keeps the last rejected one to show.
+    fn coach_moves(&mut self) {
+        while let Ok(event) = self.game.events.try_recv() {
+            let (cp, stone) = match event {
+                GameEvent::Moved { cp, stone } => (cp, stone),
+                GameEvent::Rejected { stone, illegal_move } if self.game.humans[stone as usize].is_some() => {
+                    self.rejected = Some(illegal_move.to_string());
+                    continue;
+                }
+                GameEvent::UndoRequested { stone } if self.game.humans[(-stone) as usize].is_some() => {
+                    self.undo_request = Some(stone);
+                    continue;
+                }
+                _ => continue,
+            };
+            self.rejected = None;
+            if !self.coach || self.game.humans[stone as usize].is_none() {
+                continue;
+            }
+            self.coach_warnings = coach::review_played(&self.game.board.read().unwrap(), cp);
+        }
+    }
+
+    fn toggle_analysis(&mut self) {
+        self.analyser = match self.analyser {
+            Some(_) => None,
+            None => Some(Analyser::new()),
+        };
+        self.requested_ply = None;
+    }
+
+    fn save_game(&mut self) {
+        let since_epoch = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+        let path = format!("game-{}.txt", since_epoch.as_secs());
+        let board = self.game.board.read().unwrap();
+        self.saved = Some(match replay::write_moves(&path, board.history()) {
+            Ok(()) => format!("saved to {}", path),
+            Err(err) => format!("can't save to {}: {}", path, err),
+        });
+    }
+
+    /// Load field, and once a game is loaded the ply controls with the analysis.
+    fn draw_replay_window(&mut self) {
+        let context = self.graphics.egui_platform.context();
+        let (mut load, mut toggle_analysis, mut close) = (false, false, false);
+        let analysis = self.analyser.as_ref().and_then(|analyser| analyser.latest());
+        let analysing = self.analyser.is_some();
+        let replay_path = &mut self.replay_path;
+        let replay_error = &self.replay_error;
+        let replay = &mut self.replay;
+        egui::Window::new("Replay").show(&context, |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(replay_path);
+                load = ui.button("Load").clicked();
+            });
+            if let Some(error) = replay_error {
+                ui.label(error);
+            }
+            let Some(replay) = replay else {
+                return;
+            };
+            let mut ply = replay.ply();
+            ui.add(egui::Slider::new(&mut ply, 0..=replay.len()).text("ply"));
+            ui.horizontal(|ui| {
+                if ui.button("<").clicked() {
+                    ply = ply.saturating_sub(1);
+                }
+                if ui.button(">").clicked() {
+                    ply += 1;
+                }
+            });
+            replay.seek(ply);
+            ui.label("arrows step, Home / End jump, A analysis, Esc back to the game");
+            let mut analyse = analysing;
+            toggle_analysis = ui.checkbox(&mut analyse, "John's analysis").changed();
+            match analysis.filter(|analysis| analysing && analysis.ply == replay.ply()) {
+                Some(analysis) => {
+                    ui.label(format!("best {}, score {} ({:?})", analysis.best_move, ai::describe_score(analysis.score), analysis.reason));
+                }
+                None if analysing => {
+                    ui.label("thinking...");
+                }
+                None => {}
+            }
+            close = ui.button("Back to the game").clicked();
+        });
+        if load {
+            match Replay::load(&self.replay_path) {
+                Ok(replay) => {
+                    self.replay = Some(replay);
+                    self.replay_error = None;
+                }
+                Err(err) => self.replay_error = Some(format!("can't load {}: {}", self.replay_path, err)),
+            }
+        }
+        if toggle_analysis {
+            self.toggle_analysis();
+        }
+        if close {
+            self.replay = None;
+        }
+        if let (Some(replay), Some(analyser)) = (&self.replay, &self.analyser) {
+            if self.requested_ply != Some(replay.ply()) {
+                analyser.request(replay.board());
+                self.requested_ply = Some(replay.ply());
+            }
+        }
+    }
+
+    fn swap_colours(&mut self) {
+        std::mem::swap(&mut self.setup.black, &mut self.setup.white);
+        self.new_game();
+    }
+
+    /// Intersections are `spacing` apart with a free margin of the same width for the labels.
+    fn spacing() -> f32 {
+        1. / (board_size() + 1) as f32
+    }
+
+    /// Board coordinates of the intersection, the board spans 0..1 both ways.
+    fn intersection(cp: CellPos) -> Vec2 {
+        vec2(cp.x() as f32 + 1., cp.y() as f32 + 1.) * Self::spacing()
+    }
+
+    /// Intersection closest to the mouse.
+    fn hovered_cell(&self) -> Option<CellPos> {
+        let pos = (self.mouse_pos / Self::spacing() - Vec2::ONE).round();
+        let size = board_size() as f32;
+        (pos.x >= 0. && pos.y >= 0. && pos.x < size && pos.y < size).then(|| cell(pos.x as usize, pos.y as usize))
+    }
+
+    /// Hoshi, 3 lines in from the edges and the centre on the usual boards.
+    fn star_points() -> Vec<CellPos> {
+        let size = board_size();
+        let edge = if size >= 13 { 3 } else { 2 };
+        let mut points = vec![center()];
+        if size >= 9 {
+            for x in [edge, size - 1 - edge] {
+                for y in [edge, size - 1 - edge] {
+                    points.push(cell(x, y));
+                }
+            }
+        }
+        points
+    }
+
+    fn draw_board(&mut self) {
+        let board = match &self.replay {
+            Some(replay) => replay.board().clone(),
+            None => self.game.board.read().unwrap().clone(),
+        };
+        let result = self.game.result.read().unwrap().clone();
+        let board_gtransform = GTransform::from_translation(vec2(-1., -1.)).inflate(2.);
+        let spacing = Self::spacing();
+        let size = board_size();
+        let line_width = spacing * 0.04;
+
+        let wood = Shape::from_square().apply(board_gtransform).set_color(Color::from_hex(0xDCB35C)).set_z(0.9);
+        self.graphics.add_geometry(wood.into());
+
+        let length = (size - 1) as f32 * spacing;
+        for i in 0..size {
+            let offset = (i + 1) as f32 * spacing;
+            let horizontal = GTransform::from_translation(vec2(spacing, offset));
+            let vertical = GTransform::from_translation(vec2(offset, spacing)).rotate(std::f32::consts::FRAC_PI_2);
+            for line in [horizontal, vertical] {
+                let shape = Shape::from_line(length, line_width).apply(line).apply(board_gtransform).set_color(Color::BLACK).set_z(0.8);
+                self.graphics.add_geometry(shape.into());
+            }
+        }
+        for cp in Self::star_points() {
+            let gtransform = GTransform::from_translation(Self::intersection(cp)).inflate(spacing * 0.1);
+            let shape = Shape::from_circle(12).apply(gtransform).apply(board_gtransform).set_color(Color::BLACK).set_z(0.8);
+            self.graphics.add_geometry(shape.into());
+        }
+
+        let stone_color = |stone: Stone| match stone {
+            Stone::Black => Color::from_hex(0x000000),
+            Stone::White => Color::from_hex(0xFFFFFF)
+        };
+        for x in 0..size {
+            for y in 0..size {
+                let cp = cell(x, y);
+                let Some(stone) = board[cp] else {
+                    continue;
+                };
+                let gtransform = GTransform::from_translation(Self::intersection(cp)).inflate(spacing * 0.45);
+                let shape = Shape::from_circle(20).apply(gtransform).apply(board_gtransform).set_color(stone_color(stone)).set_z(0.5);
+                self.graphics.add_geometry(shape.into());
+                // white stones would blend into the light wood
+                let outline = Shape::from_circle(20).apply(gtransform.inflate(1.06)).apply(board_gtransform).set_color(Color::from_hex(0x404040)).set_z(0.55);
+                self.graphics.add_geometry(outline.into());
+            }
+        }
 
-        let (player_move_transmitter, player_move_receiver) = mpsc::channel();
+        if let Some(last) = board.last_move() {
+            if self.replay.is_some() || matches!(result, Some(GameResult::BlackWin | GameResult::WhiteWin)) {
+                for cp in board.winning_line(last).unwrap_or_default() {
+                    let gtransform = GTransform::from_translation(Self::intersection(cp)).inflate(spacing * 0.52);
+                    let shape = Shape::from_circle(20).apply(gtransform).apply(board_gtransform).set_color(Color::from_hex(0xE03030)).set_z(0.6);
+                    self.graphics.add_geometry(shape.into());
+                }
+            }
+            if !self.show_move_numbers {
+                let gtransform = GTransform::from_translation(Self::intersection(last)).inflate(spacing * 0.12);
+                let shape = Shape::from_circle(12).apply(gtransform).apply(board_gtransform).set_color(Color::from_hex(0xE03030)).set_z(0.4);
+                self.graphics.add_geometry(shape.into());
+            }
+        }
+
+        let analysis = self.analyser.as_ref().and_then(|analyser| analyser.latest()).filter(|analysis| analysis.ply == board.history().len());
+        if let (Some(analysis), Some(_)) = (analysis, &self.replay) {
+            let gtransform = GTransform::from_translation(Self::intersection(analysis.best_move)).inflate(spacing * 0.15);
+            let shape = Shape::from_circle(12).apply(gtransform).apply(board_gtransform).set_color(Color::from_hex(0x2060E0)).set_z(0.4);
+            self.graphics.add_geometry(shape.into());
+        }
 
-        let w_actor = Box::new(actor::Player::new(player_move_receiver));
-        let b_actor = Box::new(ai::John::new(1_000_000., 2., 4));
+        let hint = self.hinter.as_ref().and_then(|hinter| hinter.latest()).filter(|hint| hint.ply == board.history().len());
+        if let (Some(hint), None) = (hint, &self.replay) {
+            let gtransform = GTransform::from_translation(Self::intersection(hint.best_move)).inflate(spacing * 0.15);
+            let shape = Shape::from_circle(12).apply(gtransform).apply(board_gtransform).set_color(Color::from_hex(0x20A040)).set_z(0.4);
+            self.graphics.add_geometry(shape.into());
+        }
+
+        let human_to_move = self.game.humans[board.turn as usize].is_some() && result.is_none() && self.replay.is_none();
+        if let Some(cp) = self.hovered_cell().filter(|cp| human_to_move && board[*cp].is_none()) {
+            let gtransform = GTransform::from_translation(Self::intersection(cp)).inflate(spacing * 0.45);
+            let shape = Shape::from_circle(20).apply(gtransform).apply(board_gtransform).set_color(stone_color(board.turn).set_alpha(0.5)).set_z(0.3);
+            self.graphics.add_geometry(shape.into());
+        }
+
+        // egui does the text, it works in points with y going down
+        let context = self.graphics.egui_platform.context();
+        let screen = context.screen_rect().size();
+        let to_screen = |pos: Vec2| egui::pos2(pos.x * screen.x, (1. - pos.y) * screen.y);
+        let painter = context.layer_painter(egui::LayerId::background());
+        let font = egui::FontId::proportional(spacing * screen.x.min(screen.y) * 0.4);
+        let text_color = egui::Color32::from_rgb(0x30, 0x20, 0x10);
+        for i in 0..size {
+            let offset = (i + 1) as f32 * spacing;
+            let column = (b'A' + i as u8) as char;
+            for y in [spacing * 0.45, 1. - spacing * 0.45] {
+                painter.text(to_screen(vec2(offset, y)), egui::Align2::CENTER_CENTER, column, font.clone(), text_color);
+            }
+            for x in [spacing * 0.45, 1. - spacing * 0.45] {
+                painter.text(to_screen(vec2(x, offset)), egui::Align2::CENTER_CENTER, i + 1, font.clone(), text_color);
+            }
+        }
+        if self.show_move_numbers {
+            for (i, cp) in board.history().iter().enumerate() {
+                let color = match board[*cp] {
+                    Some(Stone::Black) => egui::Color32::WHITE,
+                    _ => egui::Color32::BLACK,
+                };
+                let color = if Some(*cp) == board.last_move() { egui::Color32::RED } else { color };
+                painter.text(to_screen(Self::intersection(*cp)), egui::Align2::CENTER_CENTER, i + 1, font.clone(), color);
+            }
+        }
+    }
 
-        let board_clone = board.clone();
-        let game_manager_thread = std::thread::spawn(|| {
-            let game_manager = GameManager::new(board_clone, b_actor, w_actor);
-            game_manager.run();
+    /// Side picker with the strength knob of the chosen engine.
+    fn side_controls(ui: &mut egui::Ui, stone: Stone, side: &mut Side) {
+        let name = match side {
+            Side::Human => "human".to_string(),
+            Side::Engine(config) => config.name.clone(),
+        };
+        egui::ComboBox::from_label(stone.to_string()).selected_text(&name).show_ui(ui, |ui| {
+            if ui.selectable_label(name == "human", "human").clicked() {
+                *side = Side::Human;
+            }
+            for engine in ai::ENGINES {
+                if ui.selectable_label(name == engine, engine).clicked() {
+                    *side = Side::Engine(EngineConfig::new(engine));
+                }
+            }
         });
+        match side {
+            Side::Engine(config) if config.name == "john" => {
+                ui.add(egui::Slider::new(&mut config.compute, 10_000. ..= 10_000_000.).logarithmic(true).text("compute"));
+            }
+            Side::Engine(config) if config.name != "random" => {
+                ui.add(egui::Slider::new(&mut config.depth, 1..=5).text("depth"));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl App<Txts> for Gomoku {
+    async fn new(window: winit::window::Window) -> Self {
+        let connection = CONNECTION.lock().unwrap().take();
+        let setup = SETUP.lock().unwrap().take().unwrap_or_default();
+        let chat = connection.as_ref().map(|c| c.chat());
 
         Self {
-            board,
-            game_manager_thread,
+            game: Game::new(&setup, connection),
+            setup,
             graphics: Graphics::new(window).await,
-            player_move_transmitter,
             mouse_pos: Vec2::ZERO,
-            player_stone: Some(Stone::White)
+            replay: REPLAY.lock().unwrap().take(),
+            replay_path: String::new(),
+            replay_error: None,
+            analyser: None,
+            requested_ply: None,
+            hinter: None,
+            coach: false,
+            coach_warnings: vec![],
+            rejected: None,
+            undo_request: None,
+            saved: None,
+            show_move_numbers: false,
+            chat,
+            chat_input: String::new()
         }
     }
 
@@ -73,20 +562,90 @@ impl App<Txts> for Gomoku {
                 false
             },
             WindowEvent::MouseInput { button: winit::event::MouseButton::Left, state, ..} => {
-                let Some(stone) = self.player_stone else {
+                // clicking one of the windows
+                if self.graphics.egui_platform.context().wants_pointer_input() || self.replay.is_some() {
+                    return false;
+                }
+                let turn = self.game.board.read().unwrap().turn;
+                let Some(transmitter) = &self.game.humans[turn as usize] else {
                     return false;
                 };
-                if stone != self.board.read().unwrap().turn || state != &ElementState::Pressed {
+                if state != &ElementState::Pressed {
+                    return false;
+                }
+                if self.game.result.read().unwrap().is_some() {
                     return false;
                 }
 
-                let cp = self.mouse_pos * 15.;
-                let cp = cell(cp.x as usize, cp.y as usize);
+                let Some(cp) = self.hovered_cell() else {
+                    return false;
+                };
 
-                self.player_move_transmitter.send(cp).unwrap();
+                transmitter.send(Action::Move(cp)).unwrap();
 
                 false
             }
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. } => {
+                // typing into the chat
+                if self.graphics.egui_platform.context().wants_keyboard_input() {
+                    return false;
+                }
+                if let Some(replay) = &mut self.replay {
+                    match key {
+                        VirtualKeyCode::Left => replay.back(),
+                        VirtualKeyCode::Right => replay.forward(),
+                        VirtualKeyCode::Home => replay.seek(0),
+                        VirtualKeyCode::End => replay.seek(replay.len()),
+                        VirtualKeyCode::A => self.toggle_analysis(),
+                        VirtualKeyCode::Escape => self.replay = None,
+                        _ => {}
+                    }
+                    // the game underneath isn't played while it's hidden
+                    if key != &VirtualKeyCode::M {
+                        return false;
+                    }
+                }
+                if key == &VirtualKeyCode::M {
+                    self.show_move_numbers = !self.show_move_numbers;
+                    return false;
+                }
+                if self.chat.is_none() {
+                    match key {
+                        VirtualKeyCode::N => {
+                            self.new_game();
+                            return false;
+                        }
+                        VirtualKeyCode::S => {
+                            self.swap_colours();
+                            return false;
+                        }
+                        VirtualKeyCode::H => {
+                            self.hint();
+                            return false;
+                        }
+                        VirtualKeyCode::C => {
+                            self.coach = !self.coach;
+                            self.coach_warnings.clear();
+                            return false;
+                        }
+                        _ => {}
+                    }
+                }
+                let turn = self.game.board.read().unwrap().turn;
+                let Some(transmitter) = &self.game.humans[turn as usize] else {
+                    return false;
+                };
+                if self.game.result.read().unwrap().is_some() {
+                    return false;
+                }
+                let action = match key {
+                    VirtualKeyCode::R => Action::Resign,
+                    VirtualKeyCode::U => Action::RequestUndo,
+                    _ => return false
+                };
+                transmitter.send(action).unwrap();
+                false
+            }
             _ => false
         }
     }
@@ -94,40 +653,161 @@ impl App<Txts> for Gomoku {
     fn update(&mut self, dt: f32) {}
 
     fn draw(&mut self) {
-        let board = self.board.read().unwrap();
-        let board_gtransform = GTransform::from_translation(vec2(-1., -1.)).inflate(2.);
-
-        let cell_mp = 1./15.;
-
-        for x in 0..15 {
-            for y in 0..15 {
-
-                let cp = cell(x, y);
-
-                let cell_pos = vec2(x as f32 * cell_mp, y as f32 * cell_mp);
-                let cell_center = cell_pos + Vec2::ONE * cell_mp / 2.;
+        let result = self.game.result.read().unwrap().clone();
+        if let Some(result) = result {
+            let mut save = false;
+            egui::Window::new("Game over").show(&self.graphics.egui_platform.context(), |ui| {
+                ui.label(result.to_string());
+                save = ui.button("Save game").clicked();
+                if let Some(saved) = &self.saved {
+                    ui.label(saved);
+                }
+            });
+            if save {
+                self.save_game();
+            }
+        }
 
-                let cell_gtransform = GTransform::from_translation(cell_center).inflate(cell_mp * 0.95);
-                let outter_cell_gtransform = GTransform::from_translation(cell_center).inflate(cell_mp);
+        self.draw_replay_window();
 
-                let cell_shape = Shape::from_square_centered().apply(cell_gtransform).apply(board_gtransform).set_color(Color::from_hex(0x8B4513)).set_z(0.9);
-                let outter_cell_shape = Shape::from_square_centered().apply(outter_cell_gtransform).apply(board_gtransform).set_color(Color::from_hex(0x808080)).set_z(0.91);
+        self.coach_moves();
+        if let Some(rejected) = &self.rejected {
+            egui::Window::new("Illegal move").show(&self.graphics.egui_platform.context(), |ui| {
+                ui.label(format!("{}, try again", rejected));
+            });
+        }
+        if let Some(stone) = self.undo_request {
+            let mut answer = None;
+            egui::Window::new("Undo").show(&self.graphics.egui_platform.context(), |ui| {
+                ui.label(format!("{} asks to take back the last two moves", stone));
+                ui.horizontal(|ui| {
+                    if ui.button("Accept").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Decline").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+            if let Some(accept) = answer {
+                if let Some(transmitter) = &self.game.humans[(-stone) as usize] {
+                    let _ = transmitter.send(Action::AnswerUndo(accept));
+                }
+                self.undo_request = None;
+            }
+        }
+        if self.coach {
+            let warnings = &self.coach_warnings;
+            egui::Window::new("Coach").show(&self.graphics.egui_platform.context(), |ui| {
+                if warnings.is_empty() {
+                    ui.label("no problems with your last move");
+                }
+                for warning in warnings {
+                    ui.label(warning.to_string());
+                }
+            });
+        }
 
-                if let Some(stone) = board[cp] {
-                    let stone_shape = Shape::from_circle(20).apply(cell_gtransform.inflate(0.3)).apply(board_gtransform).set_color(match stone {
-                        Stone::Black => Color::from_hex(0x000000),
-                        Stone::White => Color::from_hex(0xFFFFFF)
-                    });
-                    self.graphics.add_geometry(stone_shape.into());
+        if let Some(clock) = self.game.clock.write().unwrap().as_mut() {
+            // the game can't be played while the replay covers it
+            if self.replay.is_some() {
+                clock.pause();
+            } else {
+                clock.resume();
+            }
+            egui::Window::new("Clock").show(&self.graphics.egui_platform.context(), |ui| {
+                for stone in [Stone::Black, Stone::White] {
+                    let remaining = clock.remaining(stone).as_secs_f32();
+                    let marker = if clock.running() == Some(stone) { "> " } else { "" };
+                    ui.label(format!("{}{}: {}:{:04.1}", marker, stone, (remaining / 60.) as u32, remaining % 60.));
                 }
+            });
+        }
 
-                self.graphics.add_geometry(cell_shape.into());
-                self.graphics.add_geometry(outter_cell_shape.into());
+        // the opponent on the other end won't join a new game
+        if self.chat.is_none() {
+            let (mut new_game, mut swap_colours) = (false, false);
+            let setup = &mut self.setup;
+            egui::Window::new("New game").show(&self.graphics.egui_platform.context(), |ui| {
+                Self::side_controls(ui, Stone::Black, &mut setup.black);
+                Self::side_controls(ui, Stone::White, &mut setup.white);
+                ui.label("H hint, C coach, M move numbers, U undo, R resign");
+                ui.horizontal(|ui| {
+                    new_game = ui.button("Start (N)").clicked();
+                    swap_colours = ui.button("Swap colours (S)").clicked();
+                });
+            });
+            if new_game {
+                self.new_game();
+            }
+            if swap_colours {
+                self.swap_colours();
             }
         }
+
+        if let Some(chat) = &self.chat {
+            let chat_input = &mut self.chat_input;
+            egui::Window::new("Chat").show(&self.graphics.egui_platform.context(), |ui| {
+                for line in chat.log.lock().unwrap().iter() {
+                    ui.label(line);
+                }
+                let response = ui.text_edit_singleline(chat_input);
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) && !chat_input.is_empty() {
+                    chat.send(chat_input);
+                    chat_input.clear();
+                }
+            });
+        }
+
+        self.draw_board();
     }
 }
 
 pub async fn start() {
     ellipsoid::run::<Txts, Gomoku>().await;
-}
\ No newline at end of file
+}
+
+/// Plays a local game set up by `setup`.
+pub async fn start_with(setup: Setup) {
+    *SETUP.lock().unwrap() = Some(setup);
+    start().await;
+}
+
+/// Opens the window on a recorded game.
+pub async fn start_replay(path: impl AsRef<Path>) -> io::Result<()> {
+    *REPLAY.lock().unwrap() = Some(Replay::load(path)?);
+    start().await;
+    Ok(())
+}
+
+/// Plays against whoever is on the other side of `connection`.
+pub async fn start_network(connection: Connection) {
+    *CONNECTION.lock().unwrap() = Some(connection);
+    start().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_position() {
+        let path = std::env::temp_dir().join(format!("gomoku-position-{}.txt", std::process::id()));
+        let mut setup = Setup::default();
+
+        std::fs::write(&path, "h8 h9 i9").unwrap();
+        setup.load_position(&path).unwrap();
+        assert_eq!(setup.position, [cell(7, 7), cell(7, 8), cell(8, 8)]);
+
+        // a bad position leaves the last good one in place
+        std::fs::write(&path, "h8 h9 h8").unwrap();
+        assert!(setup.load_position(&path).is_err());
+        std::fs::write(&path, "a1 a15 b1 b15 c1 c15 d1 d15 e1").unwrap();
+        assert!(setup.load_position(&path).is_err());
+        std::fs::write(&path, "h8 z99").unwrap();
+        assert!(setup.load_position(&path).is_err());
+        assert_eq!(setup.position.len(), 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
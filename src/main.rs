@@ -1,5 +1,167 @@
-use gomoku::start;
+use std::time::Duration;
+
+use gomoku::board::{Board, MAX_BOARD_SIZE, Stone, set_board_size};
+use gomoku::clock::TimeControl;
+use gomoku::network::Connection;
+use gomoku::rules::{IllegalMovePolicy, Rules};
+use gomoku::{EngineConfig, Setup, Side, start_network, start_replay, start_with};
+
+fn player_name() -> String {
+    std::env::var("USER").unwrap_or("player".to_string())
+}
+
+/// `human` or an engine like `john`, `bob:depth=4`, `john:compute=2e6,mp=2,count_iter=4,network=<file>`
+fn parse_side(text: &str) -> Side {
+    match text {
+        "human" => Side::Human,
+        engine => Side::Engine(engine.parse().unwrap_or_else(|e| panic!("{}", e))),
+    }
+}
+
+fn setup_from_flags(flags: &[String]) -> Setup {
+    let mut setup = Setup::default();
+    let mut size = 15;
+    let mut position = None;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let mut value = || flags.next().unwrap_or_else(|| panic!("{} needs a value", flag)).as_str();
+        match flag.as_str() {
+            "--black" => setup.black = parse_side(value()),
+            "--white" => setup.white = parse_side(value()),
+            "--rules" => setup.rules = match value() {
+                "standard" => Rules::Standard,
+                "freestyle" => Rules::Freestyle,
+                rules => panic!("unknown rules {}", rules),
+            },
+            "--illegal-moves" => setup.illegal_moves = Some(match value() {
+                "retry" => IllegalMovePolicy::Retry,
+                policy => policy.strip_prefix("forfeit:").and_then(|n| n.parse().ok()).map(IllegalMovePolicy::Forfeit)
+                    .unwrap_or_else(|| panic!("--illegal-moves is retry or forfeit:<attempts>, not {}", policy)),
+            }),
+            // minutes for the game + seconds added per move, like 5+5
+            "--time" => setup.time_control = Some(value().split_once('+')
+                .and_then(|(base, increment)| Some(TimeControl::Fischer {
+                    base: Duration::from_secs_f32(base.parse::<f32>().ok()? * 60.),
+                    increment: Duration::from_secs_f32(increment.parse().ok()?),
+                }))
+                .expect("--time needs minutes+seconds like 5+5")),
+            "--size" => size = value().parse().expect("--size needs a number"),
+            "--position" => position = Some(value().to_string()),
+            flag => panic!("unknown flag {}", flag),
+        }
+    }
+    // cells of the position only make sense once the size is known
+    set_board_size(size);
+    if let Some(path) = position {
+        setup.load_position(&path).unwrap_or_else(|e| panic!("can't load {}: {}", path, e));
+    }
+    setup
+}
 
 fn main() {
-    async_std::task::block_on(start());
-}
\ No newline at end of file
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.get(1).map(|s| s.as_str()) {
+        // cargo run --release -- tune [iterations]
+        Some("tune") => {
+            let iterations = args.get(2).map(|s| s.parse().unwrap()).unwrap_or(100);
+            gomoku::tuner::tune_john(iterations);
+        }
+        // cargo run --release -- selfplay [games] [output]
+        Some("selfplay") => {
+            let games = args.get(2).map(|s| s.parse().unwrap()).unwrap_or(10);
+            let path = args.get(3).map(|s| s.as_str()).unwrap_or("selfplay.txt");
+            gomoku::selfplay::generate_john(games, path);
+        }
+        // cargo run -- host [port] [black|white]
+        Some("host") => {
+            let port = args.get(2).map(|s| s.parse::<u16>().unwrap()).unwrap_or(7777);
+            let stone = match args.get(3).map(|s| s.as_str()) {
+                Some("black") => Stone::Black,
+                _ => Stone::White,
+            };
+            let connection = Connection::host(("0.0.0.0", port), &player_name(), stone).unwrap();
+            async_std::task::block_on(start_network(connection));
+        }
+        // cargo run -- join <host:port>
+        Some("join") => {
+            let addr = args.get(2).map(|s| s.as_str()).unwrap_or("127.0.0.1:7777");
+            let connection = Connection::join(addr, &player_name()).unwrap();
+            async_std::task::block_on(start_network(connection));
+        }
+        // cargo run --release -- server [port]
+        Some("server") => {
+            let port = args.get(2).map(|s| s.parse::<u16>().unwrap()).unwrap_or(7878);
+            gomoku::server::Server::bind(("0.0.0.0", port)).unwrap().run();
+        }
+        // cargo run --release -- replay <file with moves like h8 h9 i9>
+        Some("replay") => {
+            let path = args.get(2).expect("replay needs a game file");
+            async_std::task::block_on(start_replay(path)).unwrap_or_else(|e| panic!("can't load {}: {}", path, e));
+        }
+        // cargo run --release -- review <game file> [--json] [--compute 200000]
+        Some("review") => {
+            let path = args.get(2).expect("review needs a game file");
+            let mut json = false;
+            let mut compute = 200_000.;
+            let mut flags = args.iter().skip(3);
+            while let Some(flag) = flags.next() {
+                match flag.as_str() {
+                    "--json" => json = true,
+                    "--compute" => compute = flags.next().and_then(|c| c.parse().ok()).expect("--compute needs a number"),
+                    flag => panic!("unknown flag {}", flag),
+                }
+            }
+            let moves = gomoku::replay::read_moves(path).unwrap_or_else(|e| panic!("can't load {}: {}", path, e));
+            let report = gomoku::review::review_game(&moves, compute);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                print!("{}", report.to_text());
+            }
+        }
+        // cargo run --release -- bench [ordering|windows] [bob depth] [john compute]
+        Some("bench") => {
+            let depth = args.get(3).map(|s| s.parse().unwrap()).unwrap_or(3);
+            let compute = args.get(4).map(|s| s.parse().unwrap()).unwrap_or(300_000.);
+            match args.get(2).map(|s| s.as_str()) {
+                Some("windows") => gomoku::bench::narrow_windows(depth, compute),
+                _ => gomoku::bench::move_ordering(depth, compute),
+            }
+        }
+        // cargo run --release -- tui [--engine john|bob:depth=4|noob|random] [--black|--white] [--unicode]
+        Some("tui") => {
+            let mut options = gomoku::tui::TuiOptions::default();
+            let mut flags = args.iter().skip(2);
+            while let Some(flag) = flags.next() {
+                match flag.as_str() {
+                    "--engine" => {
+                        let engine = flags.next().expect("--engine needs a name");
+                        options.engine = engine.parse().unwrap_or_else(|e| panic!("{}", e));
+                    }
+                    "--black" => options.human = Stone::Black,
+                    "--white" => options.human = Stone::White,
+                    "--unicode" => options.unicode = true,
+                    flag => panic!("unknown flag {}", flag),
+                }
+            }
+            gomoku::tui::run(options);
+        }
+        // cargo run --release -- analyse "<position like 15/.../7o7/.../15 b>" [engine] [--unicode]
+        Some("analyse") => {
+            let position = args.get(2).expect("analyse needs a position");
+            // one row per line of the board
+            let rows = position.split('/').count();
+            if !(5..=MAX_BOARD_SIZE).contains(&rows) {
+                panic!("{} rows, boards have between 5 and {}", rows, MAX_BOARD_SIZE);
+            }
+            set_board_size(rows);
+            let board = Board::from_position(position).unwrap_or_else(|e| panic!("{}", e));
+            let engine = args.get(3).filter(|arg| *arg != "--unicode").map(|engine| engine.parse().unwrap_or_else(|e| panic!("{}", e)));
+            let unicode = args.iter().any(|arg| arg == "--unicode");
+            gomoku::tui::analyse(&board, &engine.unwrap_or_else(|| EngineConfig::new("john")), unicode);
+        }
+        // cargo run --release -- [--black human|<engine>] [--white human|<engine>] [--rules standard|freestyle]
+        //     [--size 15] [--position <file with moves like h8 h9 i9>]
+        _ => async_std::task::block_on(start_with(setup_from_flags(&args[1..]))),
+    }
+}
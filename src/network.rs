@@ -0,0 +1,472 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::game_manager::GameResult;
+use crate::rules::IllegalMovePolicy;
+
+use super::*;
+
+/// One line of the protocol. `ply` / `seq` is the number of moves on the board before the message applies,
+/// so that both sides can tell when they went out of sync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// sent by both sides after connecting, `stone` is what the host plays (client sends `None`)
+    Hello { ply: usize, stone: Option<Stone>, name: String },
+    Move { seq: usize, cp: CellPos },
+    /// asks the peer to resend its moves from `ply` on
+    Sync { ply: usize },
+    Undo { ply: usize },
+    UndoAccepted,
+    UndoDeclined,
+    Resign,
+    Chat(String),
+}
+
+fn stone_str(stone: Option<Stone>) -> &'static str {
+    match stone {
+        Some(Stone::Black) => "black",
+        Some(Stone::White) => "white",
+        None => "-",
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hello { ply, stone, name } => write!(f, "HELLO {} {} {}", ply, stone_str(*stone), name),
+            Self::Move { seq, cp } => write!(f, "MOVE {} {} {}", seq, cp.x(), cp.y()),
+            Self::Sync { ply } => write!(f, "SYNC {}", ply),
+            Self::Undo { ply } => write!(f, "UNDO {}", ply),
+            Self::UndoAccepted => write!(f, "UNDO_OK"),
+            Self::UndoDeclined => write!(f, "UNDO_NO"),
+            Self::Resign => write!(f, "RESIGN"),
+            Self::Chat(text) => write!(f, "CHAT {}", text),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseMessageError(String);
+
+impl fmt::Display for ParseMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid message: {}", self.0)
+    }
+}
+
+impl FromStr for Message {
+    type Err = ParseMessageError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let err = || ParseMessageError(line.to_string());
+        let (command, rest) = line.trim_end().split_once(' ').unwrap_or((line.trim_end(), ""));
+        let mut args = rest.split_whitespace();
+        let mut number = || -> Result<usize, ParseMessageError> {
+            args.next().and_then(|arg| arg.parse().ok()).ok_or_else(err)
+        };
+
+        let message = match command {
+            "HELLO" => {
+                let ply = number()?;
+                let mut parts = rest.splitn(3, ' ').skip(1);
+                let stone = match parts.next() {
+                    Some("black") => Some(Stone::Black),
+                    Some("white") => Some(Stone::White),
+                    Some("-") => None,
+                    _ => return Err(err()),
+                };
+                Self::Hello { ply, stone, name: parts.next().unwrap_or("").to_string() }
+            }
+            "MOVE" => {
+                let (seq, x, y) = (number()?, number()?, number()?);
+                if x >= board_size() || y >= board_size() {
+                    return Err(err());
+                }
+                Self::Move { seq, cp: cell(x, y) }
+            }
+            "SYNC" => Self::Sync { ply: number()? },
+            "UNDO" => Self::Undo { ply: number()? },
+            "UNDO_OK" => Self::UndoAccepted,
+            "UNDO_NO" => Self::UndoDeclined,
+            "RESIGN" => Self::Resign,
+            "CHAT" => Self::Chat(rest.to_string()),
+            _ => return Err(err()),
+        };
+        Ok(message)
+    }
+}
+
+enum Incoming {
+    Message(Message),
+    Disconnected,
+}
+
+enum Role {
+    Host(TcpListener),
+    Client(SocketAddr),
+}
+
+/// Chat lines of both sides, shared with the GUI.
+#[derive(Clone)]
+pub struct Chat {
+    writer: Arc<Mutex<TcpStream>>,
+    name: String,
+    pub log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Chat {
+    pub fn send(&self, text: &str) {
+        let text = text.replace('\n', " ");
+        self.log.lock().unwrap().push(format!("{}: {}", self.name, text));
+        // a dropped connection is handled by the game thread, the line is just lost
+        let _ = writeln!(self.writer.lock().unwrap(), "{}", Message::Chat(text));
+    }
+}
+
+/// Socket to the other instance plus a reader thread, survives the peer reconnecting.
+pub struct Connection {
+    role: Role,
+    name: String,
+    pub peer_name: String,
+    /// what the host plays, agreed on in the first hello
+    pub host_stone: Stone,
+    writer: Arc<Mutex<TcpStream>>,
+    incoming: mpsc::Receiver<Incoming>,
+    chat_log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Connection {
+    const RECONNECT_ATTEMPTS: usize = 30;
+
+    /// Waits for a client on `addr`, the host plays `stone`.
+    pub fn host(addr: impl ToSocketAddrs, name: &str, stone: Stone) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("WAITING FOR OPPONENT ON {}", listener.local_addr()?);
+        let (stream, _) = listener.accept()?;
+        let mut connection = Self::with_stream(Role::Host(listener), stream, name, stone)?;
+        connection.send(&Message::Hello { ply: 0, stone: Some(stone), name: name.to_string() })?;
+        match connection.recv() {
+            Some(Message::Hello { name, .. }) => connection.peer_name = name,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected hello")),
+        }
+        Ok(connection)
+    }
+
+    pub fn join(addr: impl ToSocketAddrs, name: &str) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or(io::ErrorKind::AddrNotAvailable)?;
+        let stream = TcpStream::connect(addr)?;
+        let mut connection = Self::with_stream(Role::Client(addr), stream, name, Stone::White)?;
+        connection.send(&Message::Hello { ply: 0, stone: None, name: name.to_string() })?;
+        match connection.recv() {
+            Some(Message::Hello { name, stone: Some(stone), .. }) => {
+                connection.peer_name = name;
+                connection.host_stone = stone;
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected hello")),
+        }
+        Ok(connection)
+    }
+
+    fn with_stream(role: Role, stream: TcpStream, name: &str, host_stone: Stone) -> io::Result<Self> {
+        let (sender, incoming) = mpsc::channel();
+        let chat_log = Arc::new(Mutex::new(vec![]));
+        spawn_reader(stream.try_clone()?, sender, chat_log.clone(), String::new());
+        Ok(Self {
+            role,
+            name: name.to_string(),
+            peer_name: String::new(),
+            host_stone,
+            writer: Arc::new(Mutex::new(stream)),
+            incoming,
+            chat_log,
+        })
+    }
+
+    /// The stone this side plays.
+    pub fn local_stone(&self) -> Stone {
+        match self.role {
+            Role::Host(_) => self.host_stone,
+            Role::Client(_) => -self.host_stone,
+        }
+    }
+
+    pub fn chat(&self) -> Chat {
+        Chat { writer: self.writer.clone(), name: self.name.clone(), log: self.chat_log.clone() }
+    }
+
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        writeln!(self.writer.lock().unwrap(), "{}", message)
+    }
+
+    /// Next game message, `None` once the peer is gone.
+    fn recv(&mut self) -> Option<Message> {
+        match self.incoming.recv() {
+            Ok(Incoming::Message(message)) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Gets a new stream from the same peer and exchanges hellos, returns the peer's ply.
+    fn reconnect(&mut self, ply: usize) -> io::Result<usize> {
+        let stream = match &self.role {
+            Role::Host(listener) => {
+                // the client gives up after its attempts, one a second, so do we
+                let deadline = Instant::now() + Duration::from_secs(Self::RECONNECT_ATTEMPTS as u64 + 1);
+                listener.set_nonblocking(true)?;
+                let accepted = loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => break Ok(stream),
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                            std::thread::sleep(Duration::from_millis(100));
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                            break Err(io::Error::new(io::ErrorKind::TimedOut, "the client didn't come back"));
+                        }
+                        Err(err) => break Err(err),
+                    }
+                };
+                listener.set_nonblocking(false)?;
+                let stream = accepted?;
+                stream.set_nonblocking(false)?;
+                stream
+            }
+            Role::Client(addr) => {
+                let mut attempts = 0;
+                loop {
+                    match TcpStream::connect(addr) {
+                        Ok(stream) => break stream,
+                        Err(err) if attempts >= Self::RECONNECT_ATTEMPTS => return Err(err),
+                        Err(_) => {
+                            attempts += 1;
+                            std::thread::sleep(Duration::from_secs(1));
+                        }
+                    }
+                }
+            }
+        };
+        let (sender, incoming) = mpsc::channel();
+        spawn_reader(stream.try_clone()?, sender, self.chat_log.clone(), self.peer_name.clone());
+        *self.writer.lock().unwrap() = stream;
+        self.incoming = incoming;
+
+        let stone = matches!(self.role, Role::Host(_)).then_some(self.host_stone);
+        self.send(&Message::Hello { ply, stone, name: self.name.clone() })?;
+        match self.recv() {
+            Some(Message::Hello { ply, .. }) => Ok(ply),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected hello")),
+        }
+    }
+}
+
+fn spawn_reader(stream: TcpStream, sender: mpsc::Sender<Incoming>, chat_log: Arc<Mutex<Vec<String>>>, mut peer_name: String) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match line.parse::<Message>() {
+                Ok(Message::Chat(text)) => chat_log.lock().unwrap().push(format!("{}: {}", peer_name, text)),
+                Ok(message) => {
+                    if let Message::Hello { name, .. } = &message {
+                        peer_name = name.clone();
+                    }
+                    if sender.send(Incoming::Message(message)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => println!("NETWORK: {}", err),
+            }
+        }
+        let _ = sender.send(Incoming::Disconnected);
+    });
+}
+
+/// Illegal moves the peer may send before it forfeits.
+const REMOTE_ATTEMPTS: usize = 3;
+
+/// The opponent sitting at the other end of a `Connection`.
+pub struct RemotePlayer {
+    connection: Connection,
+    /// moves of the local board the peer already knows about
+    synced: usize,
+}
+
+impl RemotePlayer {
+    pub fn new(connection: Connection) -> Self {
+        Self { connection, synced: 0 }
+    }
+
+    /// Sends the local moves made since the last call.
+    fn flush(&mut self, board: &Board) -> io::Result<()> {
+        self.synced = self.synced.min(board.history().len());
+        for seq in self.synced..board.history().len() {
+            self.connection.send(&Message::Move { seq, cp: board.history()[seq] })?;
+        }
+        self.synced = board.history().len();
+        Ok(())
+    }
+
+    fn resend_from(&mut self, board: &Board, ply: usize) -> io::Result<()> {
+        self.synced = ply.min(self.synced);
+        self.flush(board)
+    }
+
+    /// Next game message, reconnecting when the peer drops.
+    fn recv(&mut self, board: &Board) -> Result<Message, String> {
+        loop {
+            if let Some(message) = self.connection.recv() {
+                return Ok(message);
+            }
+            println!("NETWORK: connection lost, reconnecting");
+            let peer_ply = self.connection.reconnect(board.history().len()).map_err(|err| format!("disconnected ({})", err))?;
+            self.resend_from(board, peer_ply).map_err(|err| format!("disconnected ({})", err))?;
+        }
+    }
+}
+
+impl Actor for RemotePlayer {
+    fn next(&mut self, board: &Board) -> CellPos {
+        loop {
+            if let Action::Move(cp) = self.act(board) {
+                return cp;
+            }
+        }
+    }
+
+    fn act(&mut self, board: &Board) -> Action {
+        if let Err(err) = self.flush(board) {
+            println!("NETWORK: {}", err);
+        }
+        let ply = board.history().len();
+        loop {
+            let message = match self.recv(board) {
+                Ok(message) => message,
+                Err(reason) => return Action::Forfeit(reason),
+            };
+            match message {
+                Message::Move { seq, cp } if seq == ply => {
+                    self.synced = ply + 1;
+                    return Action::Move(cp);
+                }
+                // already have it, e.g. resent after a reconnect
+                Message::Move { seq, .. } if seq < ply => {}
+                Message::Move { seq, .. } => {
+                    println!("NETWORK: desync, got move {} at ply {}", seq, ply);
+                    let _ = self.connection.send(&Message::Sync { ply });
+                }
+                Message::Sync { ply } => {
+                    let _ = self.resend_from(board, ply);
+                }
+                Message::Undo { ply: undo_ply } if undo_ply == ply => return Action::RequestUndo,
+                Message::Undo { .. } => {
+                    let _ = self.connection.send(&Message::UndoDeclined);
+                }
+                Message::Resign => return Action::Resign,
+                message => println!("NETWORK: unexpected {}", message),
+            }
+        }
+    }
+
+    fn accept_undo(&mut self, board: &Board) -> bool {
+        let _ = self.flush(board);
+        let _ = self.connection.send(&Message::Undo { ply: board.history().len() });
+        loop {
+            match self.recv(board) {
+                Ok(Message::UndoAccepted) => {
+                    self.synced = board.history().len() - 2;
+                    return true;
+                }
+                Ok(Message::UndoDeclined) | Err(_) => return false,
+                Ok(Message::Sync { ply }) => {
+                    let _ = self.resend_from(board, ply);
+                }
+                Ok(message) => println!("NETWORK: unexpected {}", message),
+            }
+        }
+    }
+
+    fn undo_answered(&mut self, board: &Board, accepted: bool) {
+        // board is already rolled back when accepted
+        self.synced = board.history().len();
+        let _ = self.connection.send(&if accepted { Message::UndoAccepted } else { Message::UndoDeclined });
+    }
+
+    fn game_over(&mut self, board: &Board, result: &GameResult) {
+        // the winning move has to get to the peer as well
+        let _ = self.flush(board);
+        let local_stone = self.connection.local_stone();
+        if matches!(result, GameResult::Forfeit { loser, .. } | GameResult::Resignation(loser) if *loser == local_stone) {
+            let _ = self.connection.send(&Message::Resign);
+        }
+    }
+
+    /// The peer's program may get out of sync, but it doesn't get to stall the game forever.
+    fn illegal_move_policy(&self) -> IllegalMovePolicy {
+        IllegalMovePolicy::Forfeit(REMOTE_ATTEMPTS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let messages = [
+            Message::Hello { ply: 3, stone: Some(Stone::Black), name: "alice b".to_string() },
+            Message::Hello { ply: 0, stone: None, name: "bob".to_string() },
+            Message::Move { seq: 4, cp: cell(7, 8) },
+            Message::Sync { ply: 2 },
+            Message::Undo { ply: 6 },
+            Message::UndoAccepted,
+            Message::Resign,
+            Message::Chat("good luck, have fun".to_string()),
+        ];
+        for message in messages {
+            assert_eq!(message.to_string().parse(), Ok(message));
+        }
+        assert!("MOVE 1 15 0".parse::<Message>().is_err());
+    }
+
+    #[test]
+    fn test_localhost_game() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let host = std::thread::spawn(move || Connection::host(addr, "host", Stone::White).unwrap());
+        let client = loop {
+            if let Ok(client) = Connection::join(addr, "client") {
+                break client;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let host = host.join().unwrap();
+        assert_eq!(client.local_stone(), Stone::Black);
+        assert_eq!(client.peer_name, "host");
+
+        let mut host_side = RemotePlayer::new(client);
+        let mut client_side = RemotePlayer::new(host);
+
+        // host (white) moved on its board, the client's remote player has to receive it
+        let mut host_board = Board::new();
+        host_board.make_move(cell(7, 7));
+        let sender = std::thread::spawn(move || {
+            let action = client_side.act(&host_board);
+            (client_side, action)
+        });
+        assert_eq!(host_side.act(&Board::new()), Action::Move(cell(7, 7)));
+
+        let mut client_board = Board::new();
+        client_board.make_move(cell(7, 7));
+        client_board.make_move(cell(8, 8));
+        host_side.flush(&client_board).unwrap();
+
+        let (_, action) = sender.join().unwrap();
+        assert_eq!(action, Action::Move(cell(8, 8)));
+    }
+}
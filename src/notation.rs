@@ -0,0 +1,177 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::*;
+
+/// Column letters, `a` is the left edge.
+const COLUMNS: &str = "abcdefghijklmnopqrs";
+
+/// `h8`, columns go left to right, rows bottom to top like in the window.
+impl fmt::Display for CellPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", COLUMNS.as_bytes()[self.x()] as char, self.y() + 1)
+    }
+}
+
+/// `h8` or `H8` -> `cell(7, 7)`, only cells of the current board size parse.
+impl FromStr for CellPos {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} is not a cell", s);
+        let mut chars = s.chars();
+        let x = COLUMNS.find(chars.next().ok_or_else(invalid)?.to_ascii_lowercase()).ok_or_else(invalid)?;
+        let y = chars.as_str().parse::<usize>().ok().and_then(|y| y.checked_sub(1)).ok_or_else(invalid)?;
+        (x < board_size() && y < board_size()).then(|| cell(x, y)).ok_or_else(invalid)
+    }
+}
+
+/// Board with coordinates on every side, the last move is put into brackets.
+/// `{:#}` draws the stones as `●` and `○` instead of `X` and `O`.
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = board_size();
+        let header = COLUMNS[..size].to_uppercase().chars().map(|c| c.to_string()).collect::<Vec<_>>().join(" ");
+        writeln!(f, "    {}", header)?;
+        let last = self.last_move();
+        for y in (0..size).rev() {
+            write!(f, "{:>2} ", y + 1)?;
+            for x in 0..size {
+                let is_last = last == Some(cell(x, y));
+                let was_last = x > 0 && last == Some(cell(x - 1, y));
+                write!(f, "{}", if is_last { '[' } else if was_last { ']' } else { ' ' })?;
+                write!(f, "{}", match (self[cell(x, y)], f.alternate()) {
+                    (Some(Stone::Black), false) => 'X',
+                    (Some(Stone::White), false) => 'O',
+                    (Some(Stone::Black), true) => '●',
+                    (Some(Stone::White), true) => '○',
+                    (None, _) => '.',
+                })?;
+            }
+            write!(f, "{}", if last == Some(cell(size - 1, y)) { ']' } else { ' ' })?;
+            writeln!(f, "{}", y + 1)?;
+        }
+        writeln!(f, "    {}", header)
+    }
+}
+
+impl Board {
+    /// One line like `15/15/7x7/6o8/.../15 w`: rows from the top, `x` for Black, `o` for White,
+    /// a number for that many empty cells, then the side to move.
+    pub fn position(&self) -> String {
+        let size = board_size();
+        let rows = (0..size).rev().map(|y| {
+            let mut row = String::new();
+            let mut empty = 0;
+            for x in 0..size {
+                match self[cell(x, y)] {
+                    None => empty += 1,
+                    Some(stone) => {
+                        if empty > 0 {
+                            row += &empty.to_string();
+                            empty = 0;
+                        }
+                        row.push(if stone == Stone::Black { 'x' } else { 'o' });
+                    }
+                }
+            }
+            if empty > 0 {
+                row += &empty.to_string();
+            }
+            row
+        }).collect::<Vec<_>>();
+        format!("{} {}", rows.join("/"), if self.turn == Stone::White { 'w' } else { 'b' })
+    }
+
+    /// Reads `position`. The order the stones were played in isn't part of it,
+    /// so the history is made up, White and Black stones taken in turn.
+    pub fn from_position(text: &str) -> Result<Self, String> {
+        let size = board_size();
+        let (rows, turn) = text.trim().split_once(' ').ok_or("a position needs the side to move after the stones")?;
+        let turn = match turn {
+            "w" => Stone::White,
+            "b" => Stone::Black,
+            turn => return Err(format!("{} is neither w nor b", turn)),
+        };
+        let rows = rows.split('/').collect::<Vec<_>>();
+        if rows.len() != size {
+            return Err(format!("{} rows on a board of size {}", rows.len(), size));
+        }
+
+        let mut stones = [vec![], vec![]];
+        for (row, y) in rows.iter().zip((0..size).rev()) {
+            let mut x = 0;
+            let mut digits = String::new();
+            for c in row.chars().chain(std::iter::once(' ')) {
+                if c.is_ascii_digit() {
+                    digits.push(c);
+                    continue;
+                }
+                x += digits.parse::<usize>().unwrap_or(0);
+                digits.clear();
+                let stone = match c {
+                    'x' => Stone::Black,
+                    'o' => Stone::White,
+                    ' ' => break,
+                    c => return Err(format!("{} is not a stone", c)),
+                };
+                if x >= size {
+                    return Err(format!("row {} is too long", y + 1));
+                }
+                stones[stone as usize].push(cell(x, y));
+                x += 1;
+            }
+            if x != size {
+                return Err(format!("row {} has {} cells instead of {}", y + 1, x, size));
+            }
+        }
+
+        let [black, white] = stones;
+        // White moves first, so it's White's turn exactly when both have as many stones
+        let expected = if turn == Stone::White { white.len() } else { white.len().wrapping_sub(1) };
+        if black.len() != expected {
+            return Err(format!("{} White and {} Black stones with {} to move", white.len(), black.len(), turn));
+        }
+        let mut board = Board::new();
+        for (i, &cp) in white.iter().enumerate() {
+            board.make_move(cp);
+            if let Some(&cp) = black.get(i) {
+                board.make_move(cp);
+            }
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cells() {
+        assert_eq!("h8".parse(), Ok(cell(7, 7)));
+        assert_eq!("A1".parse(), Ok(cell(0, 0)));
+        assert_eq!("o15".parse(), Ok(cell(14, 14)));
+        assert!("p1".parse::<CellPos>().is_err());
+        assert!("a16".parse::<CellPos>().is_err());
+        assert!("a0".parse::<CellPos>().is_err());
+        assert_eq!(cell(7, 7).to_string(), "h8");
+    }
+
+    #[test]
+    fn test_position() {
+        let mut board = Board::new();
+        for cp in [cell(7, 7), cell(8, 8), cell(0, 14)] {
+            board.make_move(cp);
+        }
+        let position = board.position();
+        assert_eq!(position, "o14/15/15/15/15/15/8x6/7o7/15/15/15/15/15/15/15 b");
+        let read = Board::from_position(&position).unwrap();
+        assert_eq!(read.position(), position);
+        assert_eq!(read.hash, board.hash);
+
+        assert!(Board::from_position("15/15 w").is_err());
+        assert!(Board::from_position(&position.replace(" b", " w")).is_err());
+        assert!(board.to_string().contains("[O]"));
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+use super::*;
+
+/// Threat shapes of one colour along a single line, biggest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShapeKind {
+    /// exactly five in a row, longer lines don't win under the standard rules
+    Five,
+    /// `.XXXX.`, two ways to make five, can't be stopped anymore
+    OpenFour,
+    /// `XXXX` with one free end
+    SimpleFour,
+    /// `XX.XX`, `X.XXX`, `XXX.X`, five once the gap is filled
+    BrokenFour,
+    /// `.XXX.` that can still become an open four
+    OpenThree,
+    /// `.X.XX.` / `.XX.X.` that can still become an open four
+    BrokenThree,
+    /// `X.X.X`, only good for a broken four
+    SplitThree,
+    /// any other three that's short of room for an open four, like `OXXX..`
+    ClosedThree,
+    /// two stones that can still become an open three
+    OpenTwo,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    pub stone: Stone,
+    /// index into `dir`, 0..4
+    pub dir: usize,
+    pub stones: Vec<CellPos>,
+    /// empty cells between the stones
+    pub gaps: Vec<CellPos>,
+    /// moves that turn the shape into the next bigger one (a five for fours, a four for threes...)
+    pub completions: Vec<CellPos>,
+    /// an opponent stone on any of these takes the threat away, empty if one stone isn't enough
+    pub defences: Vec<CellPos>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Point {
+    Own,
+    Empty,
+    /// opponent's stone or outside of the board
+    Blocked,
+}
+
+/// Cells of every line of the board in direction `dir(i)`, `i` in 0..4, long enough to hold a five.
+/// Worked out once, the board size doesn't change after the first board is made.
+fn lines(i: usize) -> &'static [Vec<CellPos>] {
+    static LINES: OnceLock<[Vec<Vec<CellPos>>; 4]> = OnceLock::new();
+    &LINES.get_or_init(|| [0, 1, 2, 3].map(|i| {
+        let size = board_size();
+        let mut lines = vec![];
+        for x in 0..size {
+            for y in 0..size {
+                // a line starts where stepping back leaves the board
+                if cell(x, y).try_add(-dir(i)).is_some() {
+                    continue;
+                }
+                let mut line = vec![cell(x, y)];
+                while let Some(next) = line.last().unwrap().try_add(dir(i)) {
+                    line.push(next);
+                }
+                if line.len() >= 5 {
+                    lines.push(line);
+                }
+            }
+        }
+        lines
+    }))[i]
+}
+
+/// How a group of stones can grow, collected over every window the group fits in.
+#[derive(Default)]
+struct Ways {
+    completions: BTreeSet<usize>,
+    /// empty cells of each window, a defence has to be in all of them
+    windows: Vec<BTreeSet<usize>>,
+}
+
+impl Ways {
+    fn add(&mut self, completions: impl IntoIterator<Item = usize>, window: BTreeSet<usize>) {
+        self.completions.extend(completions);
+        self.windows.push(window);
+    }
+
+    fn defences(&self) -> BTreeSet<usize> {
+        let mut windows = self.windows.iter();
+        let first = windows.next().cloned().unwrap_or_default();
+        windows.fold(first, |defences, window| &defences & window)
+    }
+}
+
+fn point(line: &[CellPos], board: &Board, stone: Stone, k: isize) -> Point {
+    if k < 0 || k as usize >= line.len() {
+        return Point::Blocked;
+    }
+    match board[line[k as usize]] {
+        Some(s) if s == stone => Point::Own,
+        Some(_) => Point::Blocked,
+        None => Point::Empty,
+    }
+}
+
+/// Lines worth scanning for `stone`, the ones with at least one of its stones.
+fn lines_with(board: &Board, stone: Stone, i: usize) -> impl Iterator<Item = &'static Vec<CellPos>> + '_ {
+    lines(i).iter().filter(move |line| line.iter().any(|cp| board[*cp] == Some(stone)))
+}
+
+fn line_shapes(line: &[CellPos], board: &Board, stone: Stone, i: usize) -> Vec<Shape> {
+    let at = |k: isize| point(line, board, stone, k);
+    let len = line.len() as isize;
+    let mut shapes = vec![];
+    let mut shape = |kind: ShapeKind, stones: &[usize], completions: &BTreeSet<usize>, defences: &BTreeSet<usize>| {
+        let (first, last) = (stones[0], *stones.last().unwrap());
+        shapes.push(Shape {
+            kind,
+            stone,
+            dir: i,
+            stones: stones.iter().map(|&k| line[k]).collect(),
+            gaps: (first..last).filter(|k| !stones.contains(k)).map(|k| line[k]).collect(),
+            completions: completions.iter().map(|&k| line[k]).collect(),
+            defences: defences.iter().map(|&k| line[k]).collect(),
+        });
+    };
+    let contiguous = |stones: &[usize]| stones.last().unwrap() - stones[0] + 1 == stones.len();
+
+    // fives, exactly five
+    let mut k = 0;
+    while k < len {
+        let start = k;
+        while at(k) == Point::Own {
+            k += 1;
+        }
+        if k - start == 5 {
+            let stones = (start as usize..k as usize).collect::<Vec<_>>();
+            shape(ShapeKind::Five, &stones, &BTreeSet::new(), &BTreeSet::new());
+        }
+        k += 1;
+    }
+
+    // fours, a window of five that's one stone short and wouldn't make an overline
+    let mut fours = BTreeMap::<Vec<usize>, Ways>::new();
+    let mut split_threes = BTreeMap::<Vec<usize>, Ways>::new();
+    let mut closed_threes = BTreeMap::<Vec<usize>, Ways>::new();
+    for start in 0..=len - 5 {
+        let window = (start..start + 5).collect::<Vec<_>>();
+        if window.iter().any(|&k| at(k) == Point::Blocked) || at(start - 1) == Point::Own || at(start + 5) == Point::Own {
+            continue;
+        }
+        let stones = window.iter().filter(|&&k| at(k) == Point::Own).map(|&k| k as usize).collect::<Vec<_>>();
+        let empty = window.iter().filter(|&&k| at(k) == Point::Empty).map(|&k| k as usize).collect::<BTreeSet<_>>();
+        if stones.len() == 4 {
+            fours.entry(stones).or_default().add(empty.clone(), empty);
+        } else if stones.len() == 3 && stones == [start as usize, start as usize + 2, start as usize + 4] {
+            split_threes.entry(stones).or_default().add(empty.clone(), empty);
+        } else if stones.len() == 3 {
+            closed_threes.entry(stones).or_default().add(empty.clone(), empty);
+        }
+    }
+    for (stones, ways) in &fours {
+        let kind = match (ways.completions.len(), contiguous(stones)) {
+            (1, true) => ShapeKind::SimpleFour,
+            (1, false) => ShapeKind::BrokenFour,
+            _ => ShapeKind::OpenFour,
+        };
+        shape(kind, stones, &ways.completions, &ways.defences());
+    }
+    for (stones, ways) in &split_threes {
+        // either gap breaks it
+        shape(ShapeKind::SplitThree, stones, &ways.completions, &ways.completions);
+    }
+
+    // threes and twos, a window of six with free ends whose inside can still fill up to an open four
+    let mut threes = BTreeMap::<Vec<usize>, Ways>::new();
+    let mut twos = BTreeMap::<Vec<usize>, Ways>::new();
+    for start in 0..=len - 6 {
+        let window = (start..start + 6).collect::<Vec<_>>();
+        if window.iter().any(|&k| at(k) == Point::Blocked) || at(start - 1) == Point::Own || at(start + 6) == Point::Own {
+            continue;
+        }
+        if at(start) != Point::Empty || at(start + 5) != Point::Empty {
+            continue;
+        }
+        let inner = &window[1..5];
+        let stones = inner.iter().filter(|&&k| at(k) == Point::Own).map(|&k| k as usize).collect::<Vec<_>>();
+        let inner_empty = inner.iter().filter(|&&k| at(k) == Point::Empty).map(|&k| k as usize).collect::<BTreeSet<_>>();
+        let empty = window.iter().filter(|&&k| at(k) == Point::Empty).map(|&k| k as usize).collect::<BTreeSet<_>>();
+        match stones.len() {
+            3 => threes.entry(stones).or_default().add(inner_empty, empty),
+            2 => twos.entry(stones).or_default().add(inner_empty, empty),
+            _ => {}
+        }
+    }
+    for (stones, ways) in &threes {
+        let kind = if contiguous(stones) { ShapeKind::OpenThree } else { ShapeKind::BrokenThree };
+        shape(kind, stones, &ways.completions, &ways.defences());
+    }
+    // the fours left for the stones of an open three are part of that shape already
+    for (stones, ways) in closed_threes.iter().filter(|(stones, _)| !threes.contains_key(*stones)) {
+        shape(ShapeKind::ClosedThree, stones, &ways.completions, &ways.defences());
+    }
+    for (stones, ways) in &twos {
+        shape(ShapeKind::OpenTwo, stones, &ways.completions, &ways.defences());
+    }
+
+    shapes
+}
+
+/// Every shape `stone` has on the board, biggest first.
+pub fn shapes(board: &Board, stone: Stone) -> Vec<Shape> {
+    let mut shapes = vec![];
+    for i in 0..4 {
+        for line in lines_with(board, stone, i) {
+            shapes.extend(line_shapes(line, board, stone, i));
+        }
+    }
+    shapes.sort_by_key(|shape| shape.kind);
+    shapes
+}
+
+/// Moves that give `stone` a four (of any kind), with the direction of its line.
+/// A move can show up once per line it makes a four in.
+pub fn four_moves(board: &Board, stone: Stone) -> Vec<(CellPos, usize)> {
+    let moves = four_windows(board, stone).into_iter().map(|(cp, i, _)| (cp, i)).collect::<BTreeSet<_>>();
+    moves.into_iter().collect()
+}
+
+/// Moves that give `stone` two different cells to make five with, two fours across two lines
+/// or along the same one like the middle of `X.X.X.X`.
+pub fn double_four_moves(board: &Board, stone: Stone) -> Vec<CellPos> {
+    let mut fives = BTreeMap::<CellPos, BTreeSet<CellPos>>::new();
+    for (cp, _, five) in four_windows(board, stone) {
+        fives.entry(cp).or_default().insert(five);
+    }
+    fives.into_iter().filter(|(_, fives)| fives.len() > 1).map(|(cp, _)| cp).collect()
+}
+
+/// Every five-window that's two stones short of a five for `stone`:
+/// the move that makes it a four, its direction and the cell left for the five, both ways round.
+fn four_windows(board: &Board, stone: Stone) -> BTreeSet<(CellPos, usize, CellPos)> {
+    let mut moves = BTreeSet::new();
+    for i in 0..4 {
+        for line in lines_with(board, stone, i) {
+            let at = |k: isize| point(line, board, stone, k);
+            for start in 0..=line.len() as isize - 5 {
+                let window = start..start + 5;
+                if window.clone().any(|k| at(k) == Point::Blocked) || at(start - 1) == Point::Own || at(start + 5) == Point::Own {
+                    continue;
+                }
+                if window.clone().filter(|&k| at(k) == Point::Own).count() == 3 {
+                    let empty = window.filter(|&k| at(k) == Point::Empty).map(|k| line[k as usize]).collect::<Vec<_>>();
+                    moves.insert((empty[0], i, empty[1]));
+                    moves.insert((empty[1], i, empty[0]));
+                }
+            }
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::board_with;
+
+    use super::*;
+
+    fn kinds(board: &Board) -> Vec<ShapeKind> {
+        shapes(board, Stone::White).into_iter().map(|shape| shape.kind).filter(|kind| *kind != ShapeKind::OpenTwo).collect()
+    }
+
+    #[test]
+    fn test_shapes() {
+        let row = |xs: &[usize]| xs.iter().map(|&x| cell(x, 7)).collect::<Vec<_>>();
+
+        let board = board_with(&row(&[5, 6, 7]), &[]);
+        let threes = shapes(&board, Stone::White);
+        assert_eq!(threes[0].kind, ShapeKind::OpenThree);
+        assert_eq!(threes[0].defences, row(&[4, 8]));
+
+        let board = board_with(&row(&[5, 6, 7]), &row(&[3]));
+        assert_eq!(shapes(&board, Stone::White)[0].defences, row(&[4, 8, 9]));
+
+        let board = board_with(&row(&[5, 7, 8]), &[]);
+        let broken = &shapes(&board, Stone::White)[0];
+        assert_eq!(broken.kind, ShapeKind::BrokenThree);
+        assert_eq!(broken.gaps, row(&[6]));
+        assert_eq!(broken.defences, row(&[4, 6, 9]));
+
+        assert_eq!(kinds(&board_with(&row(&[5, 6, 7, 8]), &[])), [ShapeKind::OpenFour]);
+        assert_eq!(kinds(&board_with(&row(&[5, 6, 7, 8]), &row(&[4]))), [ShapeKind::SimpleFour]);
+        assert_eq!(kinds(&board_with(&row(&[5, 6, 8, 9]), &[])), [ShapeKind::BrokenFour]);
+        assert_eq!(kinds(&board_with(&row(&[5, 7, 9]), &[])), [ShapeKind::SplitThree]);
+        let closed = &shapes(&board_with(&row(&[5, 6, 7]), &row(&[4])), Stone::White)[0];
+        assert_eq!(closed.kind, ShapeKind::ClosedThree);
+        assert_eq!(closed.completions, row(&[8, 9]));
+        assert_eq!(kinds(&board_with(&row(&[5, 6, 7, 8, 9]), &[])), [ShapeKind::Five]);
+        assert!(kinds(&board_with(&row(&[4, 5, 6, 7, 8, 9]), &[])).is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ai::{John, Reason};
+
+use super::*;
+
+/// Moves like `h8 h9 i9` separated by whitespace, White moves first.
+pub fn read_moves(path: impl AsRef<Path>) -> io::Result<Vec<CellPos>> {
+    fs::read_to_string(path)?.split_whitespace().map(|name| {
+        name.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }).collect()
+}
+
+pub fn write_moves(path: impl AsRef<Path>, moves: &[CellPos]) -> io::Result<()> {
+    let names = moves.iter().map(|cp| cp.to_string()).collect::<Vec<_>>();
+    fs::write(path, names.join(" ") + "\n")
+}
+
+/// A recorded game and the position at the current ply, stepping just makes or unmakes moves.
+pub struct Replay {
+    moves: Vec<CellPos>,
+    board: Board,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let moves = read_moves(path)?;
+        let mut board = Board::new();
+        for cp in &moves {
+            if !board.make_move(*cp) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is played twice", cp)));
+            }
+        }
+        Ok(Self { moves, board: Board::new() })
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Moves made so far, 0 is the empty board.
+    pub fn ply(&self) -> usize {
+        self.board.history().len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    pub fn seek(&mut self, ply: usize) {
+        let ply = ply.min(self.moves.len());
+        while self.ply() > ply {
+            self.board.unmake_move(self.board.last_move().unwrap());
+        }
+        while self.ply() < ply {
+            self.board.make_move(self.moves[self.ply()]);
+        }
+    }
+
+    pub fn forward(&mut self) {
+        self.seek(self.ply() + 1);
+    }
+
+    pub fn back(&mut self) {
+        self.seek(self.ply().saturating_sub(1));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Analysis {
+    pub ply: usize,
+    pub best_move: CellPos,
+    /// from the side to move's point of view
+    pub score: i32,
+    pub reason: Reason,
+}
+
+/// Runs `John` on a thread of its own, only the newest requested position gets analysed.
+pub struct Analyser {
+    jobs: mpsc::Sender<Board>,
+    latest: Arc<Mutex<Option<Analysis>>>,
+}
+
+impl Analyser {
+    pub fn new() -> Self {
+        let (jobs, receiver) = mpsc::channel::<Board>();
+        let latest = Arc::new(Mutex::new(None));
+        let results = latest.clone();
+        std::thread::spawn(move || {
+            while let Ok(mut board) = receiver.recv() {
+                // skip everything stepped over while the last search ran
+                while let Ok(newer) = receiver.try_recv() {
+                    board = newer;
+                }
+                if board.is_full() {
+                    continue;
+                }
+                // John opens in the centre without searching, there's no score to ask it for
+                if board.free_positions().count() == board_size() * board_size() {
+                    *results.lock().unwrap() = Some(Analysis { ply: 0, best_move: center(), score: 0, reason: Reason::BobsEval });
+                    continue;
+                }
+                let mut john = John::new(300_000., 2., 4);
+                john.debug = false;
+                let best_move = john.next(&board);
+                let (Some(score), Some(reason)) = (john.last_score(), john.last_reason()) else {
+                    continue;
+                };
+                *results.lock().unwrap() = Some(Analysis { ply: board.history().len(), best_move, score, reason });
+            }
+        });
+        Self { jobs, latest }
+    }
+
+    pub fn request(&self, board: &Board) {
+        self.jobs.send(board.clone()).unwrap();
+    }
+
+    /// The last finished analysis, possibly of a different ply.
+    pub fn latest(&self) -> Option<Analysis> {
+        *self.latest.lock().unwrap()
+    }
+}
+
+impl Default for Analyser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek() {
+        let path = std::env::temp_dir().join(format!("gomoku-replay-{}.txt", std::process::id()));
+        let moves = [cell(7, 7), cell(7, 8), cell(8, 8)];
+        write_moves(&path, &moves).unwrap();
+        let mut replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.ply(), 0);
+        replay.seek(10);
+        assert_eq!(replay.board().history(), &moves);
+        replay.back();
+        replay.back();
+        assert_eq!(replay.board().history(), &moves[..1]);
+        assert_eq!(replay.board()[cell(7, 8)], None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_analyse_empty_board() {
+        let analyser = Analyser::new();
+        analyser.request(&Board::new());
+        let start = std::time::Instant::now();
+        while analyser.latest().is_none() && start.elapsed() < std::time::Duration::from_secs(5) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(analyser.latest().is_some_and(|analysis| analysis.ply == 0 && analysis.best_move == center()));
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::ai::{describe_score, from_child, is_loss, is_win, John, LOST, WIN};
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    /// threw away a win or walked into a forced loss
+    Blunder,
+}
+
+/// Scores are from the point of view of the side that made the move.
+#[derive(Clone, Debug, Serialize)]
+pub struct MoveReview {
+    /// 1 is the first move
+    pub ply: usize,
+    pub stone: String,
+    pub played: String,
+    /// the best the mover could get before playing
+    pub score_before: i32,
+    /// what the mover is left with after playing
+    pub score_after: i32,
+    pub best: Option<String>,
+    /// why the search after the move settled on its score
+    pub reason: String,
+    pub judgement: Option<Judgement>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    pub moves: Vec<MoveReview>,
+}
+
+/// Eval points lost before a move counts as an inaccuracy / mistake.
+const INACCURACY: i32 = 100;
+const MISTAKE: i32 = 300;
+
+fn judge(before: i32, after: i32) -> Option<Judgement> {
+    if (is_win(before) && !is_win(after)) || (is_loss(after) && !is_loss(before)) {
+        return Some(Judgement::Blunder);
+    }
+    match before - after {
+        drop if drop >= MISTAKE => Some(Judgement::Mistake),
+        drop if drop >= INACCURACY => Some(Judgement::Inaccuracy),
+        _ => None,
+    }
+}
+
+/// Searches every position of the game with `compute` before and after the move.
+pub fn review_game(moves: &[CellPos], compute: f32) -> Report {
+    let mut john = John::new(compute, 2., 4);
+    john.debug = false;
+    let mut board = Board::new();
+    let mut report = Report::default();
+
+    for (i, &mv) in moves.iter().enumerate() {
+        let stone = board.turn;
+        // nothing to search around on the empty board
+        let (score_before, best) = match board.history().is_empty() {
+            true => (0, Some(center())),
+            false => {
+                let (score, best, _) = john.minimax(&mut board, LOST - 1, WIN + 1, compute);
+                (score, best)
+            }
+        };
+        board.make_move(mv);
+        let (score_after, reason) = if board.check_win_from(mv) {
+            (WIN, "five".to_string())
+        } else {
+            let (score, _, reason) = john.minimax(&mut board, LOST - 1, WIN + 1, compute);
+            (from_child(score), format!("{:?}", reason))
+        };
+        let judgement = judge(score_before, score_after);
+        report.moves.push(MoveReview {
+            ply: i + 1,
+            stone: stone.to_string(),
+            played: mv.to_string(),
+            score_before,
+            score_after,
+            best: best.filter(|best| judgement.is_some() && *best != mv).map(|best| best.to_string()),
+            reason,
+            judgement,
+        });
+    }
+    report
+}
+
+impl Report {
+    /// Only the flagged moves, one per line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for review in self.moves.iter().filter(|review| review.judgement.is_some()) {
+            write!(text, "{:>3}. {} {}: {:?}, {} -> {} ({})", review.ply, review.stone, review.played,
+                review.judgement.unwrap(), describe_score(review.score_before), describe_score(review.score_after), review.reason).unwrap();
+            if let Some(best) = &review.best {
+                write!(text, ", better {}", best).unwrap();
+            }
+            text.push('\n');
+        }
+        if text.is_empty() {
+            text += "no inaccuracies found\n";
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judge() {
+        assert_eq!(judge(WIN, 50), Some(Judgement::Blunder));
+        assert_eq!(judge(0, LOST), Some(Judgement::Blunder));
+        assert_eq!(judge(LOST, LOST), None);
+        assert_eq!(judge(WIN, WIN - 4), None);
+        assert_eq!(judge(300, 0), Some(Judgement::Mistake));
+        assert_eq!(judge(120, 0), Some(Judgement::Inaccuracy));
+        assert_eq!(judge(10, 0), None);
+    }
+
+    #[test]
+    fn test_review_game() {
+        // Black leaves White's open three on row 8 alone with o1 and loses to the open four at k8
+        let moves = ["h8", "a1", "i8", "a15", "j8", "o1", "k8"].map(|name| name.parse().unwrap());
+        let report = review_game(&moves, 3_000.);
+        assert_eq!(report.moves.len(), moves.len());
+
+        let blunder = &report.moves[5];
+        assert_eq!((blunder.stone.as_str(), blunder.played.as_str()), ("Black", "o1"));
+        assert_eq!(blunder.judgement, Some(Judgement::Blunder));
+        // White's open four at k8, Black blocks one end and White fives at the other
+        assert_eq!(describe_score(blunder.score_after), "loss in 2");
+        assert!(matches!(blunder.best.as_deref(), Some("g8" | "k8" | "l8" | "f8")));
+
+        // White only cashed in
+        assert_eq!(report.moves[6].judgement, None);
+        assert!(report.to_text().contains("o1: Blunder"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rules {
+    /// exactly five in a row wins, overlines don't
+    #[default]
+    Standard,
+    /// five or more in a row wins
+    Freestyle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IllegalMove {
+    Occupied(CellPos),
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Occupied(cp) => write!(f, "{} is occupied", cp),
+        }
+    }
+}
+
+impl Rules {
+    pub fn validate(&self, board: &Board, cp: CellPos) -> Result<(), IllegalMove> {
+        if board[cp].is_some() {
+            return Err(IllegalMove::Occupied(cp));
+        }
+        Ok(())
+    }
+
+    /// Whether the stone at `cp` completes a winning line.
+    pub fn is_win(&self, board: &Board, cp: CellPos) -> bool {
+        match self {
+            Self::Standard => board.check_win_from(cp),
+            Self::Freestyle => {
+                if board[cp].is_none() {
+                    return false;
+                }
+                let dir_lengths = board.compute_dir_lengths_from(cp);
+                (0..4).any(|i| dir_lengths[i] + dir_lengths[i + 4] > 5)
+            }
+        }
+    }
+}
+
+/// What `GameManager` does when an actor returns an illegal move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IllegalMovePolicy {
+    /// ask again as often as it takes
+    Retry,
+    /// forfeit once the actor made this many illegal moves
+    Forfeit(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut board = Board::new();
+        board.make_move(cell(7, 7));
+        for rules in [Rules::Standard, Rules::Freestyle] {
+            assert_eq!(rules.validate(&board, cell(7, 7)), Err(IllegalMove::Occupied(cell(7, 7))));
+            assert_eq!(rules.validate(&board, cell(7, 8)), Ok(()));
+        }
+    }
+
+    /// White plays `row` on row 8 in that order, Black answers on row 1.
+    fn white_row(row: &[usize]) -> Board {
+        let mut board = Board::new();
+        for (i, x) in row.iter().enumerate() {
+            board.make_move(cell(*x, 7));
+            if i + 1 < row.len() {
+                board.make_move(cell(*x, 0));
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_overline() {
+        // the last stone joins two runs into six
+        let six = white_row(&[2, 3, 4, 6, 7, 5]);
+        assert!(!Rules::Standard.is_win(&six, cell(5, 7)));
+        assert!(Rules::Freestyle.is_win(&six, cell(5, 7)));
+
+        let five = white_row(&[2, 3, 4, 6, 5]);
+        assert!(Rules::Standard.is_win(&five, cell(5, 7)));
+        assert!(Rules::Freestyle.is_win(&five, cell(5, 7)));
+    }
+}
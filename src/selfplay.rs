@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use rand::seq::IteratorRandom;
+
+use crate::ai::John;
+use crate::arena::random_opening;
+
+use super::*;
+
+/// One searched position of a self-play game.
+pub struct Record {
+    pub board: Board,
+    /// score the actor reported for `mv`, from the side to move's point of view
+    pub score: Option<i32>,
+    pub mv: CellPos,
+    /// 1 side to move won, -1 lost, 0 draw
+    pub result: i8,
+}
+
+impl Record {
+    /// `<board_size² cells row by row from y = 0, x black, o white, . empty> <b|w to move> <x>,<y> <score|-> <result>`
+    pub fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let mut cells = String::with_capacity(board_size() * board_size());
+        for y in 0..board_size() {
+            for x in 0..board_size() {
+                cells.push(match self.board[cell(x, y)] {
+                    Some(Stone::Black) => 'x',
+                    Some(Stone::White) => 'o',
+                    None => '.',
+                });
+            }
+        }
+        let turn = match self.board.turn {
+            Stone::Black => 'b',
+            Stone::White => 'w',
+        };
+        let score = self.score.map(|s| s.to_string()).unwrap_or("-".to_string());
+        writeln!(writer, "{} {} {},{} {} {}", cells, turn, self.mv.x(), self.mv.y(), score, self.result)
+    }
+}
+
+pub struct SelfPlay {
+    pub opening_stones: usize,
+    /// chance that the played move is swapped for a random candidate, the "temperature" keeping games apart
+    pub noise: f64,
+    /// noise is only applied this early into the game
+    pub noise_plies: usize,
+}
+
+impl Default for SelfPlay {
+    fn default() -> Self {
+        Self {
+            opening_stones: 3,
+            noise: 0.1,
+            noise_plies: 12,
+        }
+    }
+}
+
+impl SelfPlay {
+    /// Plays `actor` against itself from a random opening, returns every searched position.
+    pub fn play<R: Rng + ?Sized>(&self, actor: &mut dyn Actor, rng: &mut R) -> Vec<Record> {
+        let mut board = random_opening(self.opening_stones, rng);
+        let mut records = vec![];
+        let mut ply = 0;
+
+        let winner = loop {
+            if board.is_full() {
+                break None;
+            }
+            let mv = actor.next(&board);
+            records.push(Record { board: board.clone(), score: actor.last_score(), mv, result: 0 });
+
+            // the record keeps what the actor chose, only the game takes the detour
+            let mut played = mv;
+            if ply < self.noise_plies && rng.gen_bool(self.noise) {
+                if let Some(random_mv) = board.candidates().choose(rng) {
+                    played = random_mv;
+                }
+            }
+
+            if !board.make_move(played) {
+                break Some(-board.turn);
+            }
+            if board.check_win_from(played) {
+                break Some(-board.turn);
+            }
+            ply += 1;
+        };
+
+        if let Some(winner) = winner {
+            for record in records.iter_mut() {
+                record.result = if record.board.turn == winner { 1 } else { -1 };
+            }
+        }
+        records
+    }
+}
+
+/// Generates `games` self-play games of `John` into `path`.
+pub fn generate_john(games: usize, path: &str) {
+    let mut john = John::new(100_000., 2., 4);
+    john.debug = false;
+
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+    let mut rng = rand::thread_rng();
+    let self_play = SelfPlay::default();
+
+    for game in 0..games {
+        let records = self_play.play(&mut john, &mut rng);
+        for record in &records {
+            record.write(&mut writer).unwrap();
+        }
+        writer.flush().unwrap();
+        println!("GAME {}: {} positions", game + 1, records.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_row_by_row() {
+        let mut board = Board::new();
+        board.make_move(cell(1, 0));
+        let record = Record { board, score: None, mv: cell(0, 1), result: 0 };
+        let mut line = vec![];
+        record.write(&mut line).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        // White's stone on x = 1 of the first row, the move x = 0 of the second one
+        assert!(line.starts_with(".o."));
+        assert!(line.ends_with(" b 0,1 - 0\n"));
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use crate::actor::Player;
+use crate::game_manager::{GameEvent, GameResult};
+
+use super::*;
+
+/// What clients send, one JSON object per websocket text message, e.g. `{"type": "move", "x": 7, "y": 7}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    List,
+    /// each side is `human` (open seat) or an engine name, see `ai::engine`
+    Create { black: String, white: String },
+    Join { game: u32, stone: String, name: Option<String> },
+    Spectate { game: u32 },
+    Move { x: usize, y: usize },
+    Resign,
+    Undo,
+    /// reply to `undo_requested`, the game waits for it
+    AnswerUndo { accept: bool },
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Games { games: Vec<GameInfo> },
+    Created { game: u32 },
+    Joined { game: u32, stone: String },
+    /// full position, sent on join / spectate and after undos
+    State { game: u32, moves: Vec<[usize; 2]>, turn: String, result: Option<String> },
+    Moved { game: u32, x: usize, y: usize, stone: String },
+    /// sent to the opponent of `stone` only, answered with `answer_undo`
+    UndoRequested { game: u32, stone: String },
+    Over { game: u32, result: String, winner: Option<String> },
+    Error { message: String },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GameInfo {
+    pub game: u32,
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub moves: usize,
+    pub result: Option<String>,
+}
+
+fn stone_name(stone: Stone) -> String {
+    stone.to_string().to_lowercase()
+}
+
+fn parse_stone(name: &str) -> Option<Stone> {
+    match name {
+        "black" => Some(Stone::Black),
+        "white" => Some(Stone::White),
+        _ => None,
+    }
+}
+
+fn error(message: impl Into<String>) -> ServerMessage {
+    ServerMessage::Error { message: message.into() }
+}
+
+/// One game, its `GameManager` starts once both seats are taken.
+struct Room {
+    id: u32,
+    board: Arc<RwLock<Board>>,
+    state: Mutex<RoomState>,
+}
+
+struct RoomState {
+    /// indexed by `Stone as usize`, `None` while the seat is open
+    names: [Option<String>; 2],
+    actors: [Option<Box<dyn Actor>>; 2],
+    /// players and spectators, dropped once their connection closes
+    listeners: Vec<mpsc::Sender<ServerMessage>>,
+    /// the seated clients on their own, indexed by `Stone as usize`
+    players: [Option<mpsc::Sender<ServerMessage>>; 2],
+    result: Option<GameResult>,
+}
+
+impl Room {
+    fn info(&self) -> GameInfo {
+        let state = self.state.lock().unwrap();
+        GameInfo {
+            game: self.id,
+            black: state.names[Stone::Black as usize].clone(),
+            white: state.names[Stone::White as usize].clone(),
+            moves: self.board.read().unwrap().history().len(),
+            result: state.result.as_ref().map(|r| r.to_string()),
+        }
+    }
+
+    fn state_message(&self) -> ServerMessage {
+        let board = self.board.read().unwrap();
+        ServerMessage::State {
+            game: self.id,
+            moves: board.history().iter().map(|cp| [cp.x(), cp.y()]).collect(),
+            turn: stone_name(board.turn),
+            result: self.state.lock().unwrap().result.as_ref().map(|r| r.to_string()),
+        }
+    }
+
+    /// Built before the state is locked, messages like `state_message` lock it themselves.
+    fn broadcast(&self, message: ServerMessage) {
+        self.state.lock().unwrap().listeners.retain(|listener| listener.send(message.clone()).is_ok());
+    }
+
+    /// Takes a seat, starts the game when it was the last open one.
+    fn seat(self: &Arc<Self>, stone: Stone, name: String, actor: Box<dyn Actor>) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.names[stone as usize].is_some() {
+            return Err(format!("{} is already taken", stone_name(stone)));
+        }
+        state.names[stone as usize] = Some(name);
+        state.actors[stone as usize] = Some(actor);
+        if state.actors.iter().all(|actor| actor.is_some()) {
+            let black = state.actors[Stone::Black as usize].take().unwrap();
+            let white = state.actors[Stone::White as usize].take().unwrap();
+            drop(state);
+            self.start(black, white);
+        }
+        Ok(())
+    }
+
+    fn start(self: &Arc<Self>, black: Box<dyn Actor>, white: Box<dyn Actor>) {
+        let mut game_manager = GameManager::new(self.board.clone(), black, white);
+        let events = game_manager.subscribe();
+        std::thread::spawn(move || game_manager.run());
+
+        let room = self.clone();
+        std::thread::spawn(move || {
+            for event in events {
+                match event {
+                    GameEvent::Moved { cp, stone } => room.broadcast(ServerMessage::Moved {
+                        game: room.id, x: cp.x(), y: cp.y(), stone: stone_name(stone)
+                    }),
+                    GameEvent::UndoRequested { stone } => {
+                        if let Some(player) = &room.state.lock().unwrap().players[(-stone) as usize] {
+                            let _ = player.send(ServerMessage::UndoRequested { game: room.id, stone: stone_name(stone) });
+                        }
+                    }
+                    GameEvent::Undone => room.broadcast(room.state_message()),
+                    // the board didn't change, only the player has to know
+                    GameEvent::Rejected { stone, illegal_move } => {
+                        if let Some(player) = &room.state.lock().unwrap().players[stone as usize] {
+                            let _ = player.send(error(illegal_move.to_string()));
+                        }
+                    }
+                    GameEvent::Over(result) => {
+                        room.state.lock().unwrap().result = Some(result.clone());
+                        room.broadcast(ServerMessage::Over {
+                            game: room.id, result: result.to_string(), winner: result.winner().map(stone_name)
+                        });
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Hosts any number of games over websockets, one thread per connection.
+pub struct Server {
+    listener: TcpListener,
+    rooms: Arc<Mutex<HashMap<u32, Arc<Room>>>>,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr)?, rooms: Default::default() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn run(self) {
+        println!("SERVER LISTENING ON {}", self.listener.local_addr().unwrap());
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let rooms = self.rooms.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = Client::new(rooms).serve(stream) {
+                    println!("SERVER: client error {}", err);
+                }
+            });
+        }
+    }
+}
+
+/// Server side of one websocket connection.
+struct Client {
+    rooms: Arc<Mutex<HashMap<u32, Arc<Room>>>>,
+    outgoing: mpsc::Sender<ServerMessage>,
+    outgoing_receiver: mpsc::Receiver<ServerMessage>,
+    /// the game and stone this client plays, with the channel into its `Player`
+    seat: Option<(Arc<Room>, Stone, mpsc::Sender<Action>)>,
+}
+
+impl Client {
+    fn new(rooms: Arc<Mutex<HashMap<u32, Arc<Room>>>>) -> Self {
+        let (outgoing, outgoing_receiver) = mpsc::channel();
+        Self { rooms, outgoing, outgoing_receiver, seat: None }
+    }
+
+    /// The errors are boxed, a bare `tungstenite::Error` is too large to pass around by value.
+    fn serve(mut self, stream: TcpStream) -> Result<(), Box<tungstenite::Error>> {
+        let mut socket = tungstenite::accept(stream).map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => err,
+            tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+        })?;
+        // reads time out so that broadcasts get written in between
+        socket.get_ref().set_read_timeout(Some(Duration::from_millis(50))).map_err(tungstenite::Error::from)?;
+
+        let result = self.serve_socket(&mut socket);
+        // leaving mid-game forfeits, otherwise the game thread waits forever
+        if let Some((_, _, actions)) = &self.seat {
+            let _ = actions.send(Action::Forfeit("disconnected".to_string()));
+        }
+        result
+    }
+
+    fn serve_socket(&mut self, socket: &mut WebSocket<TcpStream>) -> Result<(), Box<tungstenite::Error>> {
+        loop {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(message) => self.handle(message),
+                        Err(err) => Some(error(err.to_string())),
+                    };
+                    if let Some(reply) = reply {
+                        self.outgoing.send(reply).unwrap();
+                    }
+                }
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+            while let Ok(message) = self.outgoing_receiver.try_recv() {
+                socket.send(Message::Text(serde_json::to_string(&message).unwrap()))?;
+            }
+        }
+    }
+
+    fn room(&self, game: u32) -> Result<Arc<Room>, ServerMessage> {
+        self.rooms.lock().unwrap().get(&game).cloned().ok_or_else(|| error(format!("no game {}", game)))
+    }
+
+    fn handle(&mut self, message: ClientMessage) -> Option<ServerMessage> {
+        let reply = match message {
+            ClientMessage::List => {
+                let mut games = self.rooms.lock().unwrap().values().map(|room| room.info()).collect::<Vec<_>>();
+                games.sort_by_key(|info| info.game);
+                ServerMessage::Games { games }
+            }
+            ClientMessage::Create { black, white } => {
+                let mut rooms = self.rooms.lock().unwrap();
+                let id = rooms.len() as u32 + 1;
+                let room = Arc::new(Room {
+                    id,
+                    board: Arc::new(RwLock::new(Board::new())),
+                    state: Mutex::new(RoomState { names: [None, None], actors: [None, None], listeners: vec![], players: [None, None], result: None }),
+                });
+                for (stone, side) in [(Stone::Black, &black), (Stone::White, &white)] {
+                    if side == "human" {
+                        continue;
+                    }
+                    let Some(engine) = ai::engine(side, true) else {
+                        return Some(error(format!("unknown engine {}", side)));
+                    };
+                    room.seat(stone, side.clone(), engine).unwrap();
+                }
+                rooms.insert(id, room);
+                ServerMessage::Created { game: id }
+            }
+            ClientMessage::Join { game, stone, name } => {
+                if self.seat.is_some() {
+                    return Some(error("already playing"));
+                }
+                let Some(stone) = parse_stone(&stone) else {
+                    return Some(error(format!("unknown stone {}", stone)));
+                };
+                let room = match self.room(game) {
+                    Ok(room) => room,
+                    Err(err) => return Some(err),
+                };
+                let (actions, receiver) = mpsc::channel();
+                if let Err(err) = room.seat(stone, name.unwrap_or("human".to_string()), Box::new(Player::new(receiver))) {
+                    return Some(error(err));
+                }
+                let mut state = room.state.lock().unwrap();
+                state.listeners.push(self.outgoing.clone());
+                state.players[stone as usize] = Some(self.outgoing.clone());
+                drop(state);
+                self.outgoing.send(ServerMessage::Joined { game, stone: stone_name(stone) }).unwrap();
+                self.seat = Some((room.clone(), stone, actions));
+                room.state_message()
+            }
+            ClientMessage::Spectate { game } => {
+                let room = match self.room(game) {
+                    Ok(room) => room,
+                    Err(err) => return Some(err),
+                };
+                room.state.lock().unwrap().listeners.push(self.outgoing.clone());
+                room.state_message()
+            }
+            ClientMessage::Move { x, y } => {
+                if x >= board_size() || y >= board_size() {
+                    return Some(error("move outside of the board"));
+                }
+                return self.act(Action::Move(cell(x, y)));
+            }
+            ClientMessage::Resign => return self.act(Action::Resign),
+            ClientMessage::Undo => return self.act(Action::RequestUndo),
+            // asked on the opponent's turn, `act` would turn it away
+            ClientMessage::AnswerUndo { accept } => {
+                let Some((_, _, actions)) = &self.seat else {
+                    return Some(error("not playing"));
+                };
+                actions.send(Action::AnswerUndo(accept)).ok();
+                return None;
+            }
+        };
+        Some(reply)
+    }
+
+    /// Passes the action to this client's `Player`, only on its own turn so that nothing queues up.
+    fn act(&self, action: Action) -> Option<ServerMessage> {
+        let Some((room, stone, actions)) = &self.seat else {
+            return Some(error("not playing"));
+        };
+        if room.state.lock().unwrap().result.is_some() {
+            return Some(error("game is over"));
+        }
+        if room.board.read().unwrap().turn != *stone {
+            return Some(error("not your turn"));
+        }
+        actions.send(action).ok();
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type ClientSocket = WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>;
+
+    fn send(socket: &mut ClientSocket, json: &str) {
+        socket.send(Message::Text(json.to_string())).unwrap();
+    }
+
+    /// Skips messages until one of the given type arrives.
+    fn expect(socket: &mut ClientSocket, kind: &str) -> serde_json::Value {
+        loop {
+            let Message::Text(text) = socket.read().unwrap() else {
+                continue;
+            };
+            let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if value["type"] == kind {
+                return value;
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_players_and_spectator() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        std::thread::spawn(move || server.run());
+
+        let (mut white, _) = tungstenite::connect(&url).unwrap();
+        let (mut black, _) = tungstenite::connect(&url).unwrap();
+        let (mut spectator, _) = tungstenite::connect(&url).unwrap();
+
+        send(&mut white, r#"{"type": "create", "black": "human", "white": "human"}"#);
+        let game = expect(&mut white, "created")["game"].as_u64().unwrap();
+
+        send(&mut white, &format!(r#"{{"type": "join", "game": {game}, "stone": "white"}}"#));
+        expect(&mut white, "joined");
+        send(&mut black, &format!(r#"{{"type": "join", "game": {game}, "stone": "black"}}"#));
+        expect(&mut black, "joined");
+        send(&mut spectator, &format!(r#"{{"type": "spectate", "game": {game}}}"#));
+        expect(&mut spectator, "state");
+
+        send(&mut black, r#"{"type": "move", "x": 7, "y": 7}"#);
+        assert_eq!(expect(&mut black, "error")["message"], "not your turn");
+
+        send(&mut white, r#"{"type": "move", "x": 7, "y": 7}"#);
+        let moved = expect(&mut spectator, "moved");
+        assert_eq!((moved["x"].as_u64(), moved["stone"].as_str()), (Some(7), Some("white")));
+
+        send(&mut black, r#"{"type": "move", "x": 7, "y": 7}"#);
+        assert_eq!(expect(&mut black, "error")["message"], "h8 is occupied");
+        send(&mut black, r#"{"type": "move", "x": 8, "y": 8}"#);
+        expect(&mut spectator, "moved");
+
+        // Black has to agree, then both moves are taken back and everyone gets the position again
+        send(&mut white, r#"{"type": "undo"}"#);
+        assert_eq!(expect(&mut black, "undo_requested")["stone"], "white");
+        send(&mut black, r#"{"type": "answer_undo", "accept": true}"#);
+        let state = expect(&mut spectator, "state");
+        assert_eq!((state["moves"].as_array().map(Vec::len), state["turn"].as_str()), (Some(0), Some("white")));
+
+        send(&mut white, r#"{"type": "move", "x": 7, "y": 7}"#);
+        expect(&mut spectator, "moved");
+
+        send(&mut black, r#"{"type": "resign"}"#);
+        assert_eq!(expect(&mut spectator, "over")["winner"], "white");
+    }
+}
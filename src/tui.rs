@@ -0,0 +1,182 @@
+use std::io::{self, BufRead, Write};
+
+use crate::actor::Player;
+use crate::ai::{EngineConfig, John};
+use crate::game_manager::GameEvent;
+
+use super::*;
+
+pub struct TuiOptions {
+    pub human: Stone,
+    pub engine: EngineConfig,
+    /// `●`/`○` instead of `X`/`O`
+    pub unicode: bool,
+}
+
+impl Default for TuiOptions {
+    fn default() -> Self {
+        Self { human: Stone::White, engine: EngineConfig::new("john"), unicode: false }
+    }
+}
+
+/// Board as text, `●`/`○` stones with `unicode`.
+fn render(board: &Board, unicode: bool) -> String {
+    match unicode {
+        true => format!("{:#}", board),
+        false => format!("{}", board),
+    }
+}
+
+const HELP: &str = "commands: <cell> (e.g. h8), undo, hint, coach, position, resign, help, quit";
+
+enum Input {
+    Line(String),
+    Event(GameEvent),
+}
+
+/// Prints the position and what `engine` plays in it.
+pub fn analyse(board: &Board, engine: &EngineConfig, unicode: bool) {
+    print!("{}", render(board, unicode));
+    println!("{}", board.position());
+    if board.is_full() {
+        return;
+    }
+    let mut actor = engine.build(false);
+    let cp = actor.next(board);
+    match actor.last_score() {
+        Some(score) => println!("{} plays {}, {}", engine.name, cp, ai::describe_score(score)),
+        None => println!("{} plays {}", engine.name, cp),
+    }
+}
+
+/// Plays a game in the terminal against one of the built-in engines.
+pub fn run(options: TuiOptions) {
+    let board = Arc::new(RwLock::new(Board::new()));
+    let (actions, player_receiver) = mpsc::channel();
+    let player = Box::new(Player::new(player_receiver));
+    let engine = options.engine.build(false);
+    let (black, white) = match options.human {
+        Stone::Black => (player as Box<dyn Actor>, engine),
+        Stone::White => (engine, player as Box<dyn Actor>),
+    };
+
+    let mut game_manager = GameManager::new(board.clone(), black, white);
+    game_manager.debug = false;
+    let events = game_manager.subscribe();
+
+    // moves of the engine and typed lines both wake the loop below
+    let (input_sender, inputs) = mpsc::channel();
+    let event_sender = input_sender.clone();
+    std::thread::spawn(move || {
+        for event in events {
+            if event_sender.send(Input::Event(event)).is_err() {
+                return;
+            }
+        }
+    });
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if input_sender.send(Input::Line(line)).is_err() {
+                return;
+            }
+        }
+    });
+    std::thread::spawn(move || game_manager.run());
+
+    println!("You play {}. {}", options.human, HELP);
+    let prompt = |board: &Board| {
+        print!("{}", render(board, options.unicode));
+        if board.turn == options.human {
+            print!("your move> ");
+        } else {
+            println!("{} is thinking...", options.engine.name);
+        }
+        io::stdout().flush().unwrap();
+    };
+    prompt(&board.read().unwrap());
+
+    let mut coach = false;
+    for input in inputs {
+        match input {
+            Input::Event(GameEvent::Over(result)) => {
+                print!("{}", render(&board.read().unwrap(), options.unicode));
+                println!("{}", result);
+                return;
+            }
+            Input::Event(GameEvent::Moved { cp, stone }) => {
+                println!("{} played {}", stone, cp);
+                if coach && stone == options.human {
+                    for warning in coach::review_played(&board.read().unwrap(), cp) {
+                        println!("coach: {}", warning);
+                    }
+                }
+                prompt(&board.read().unwrap());
+            }
+            Input::Event(GameEvent::Rejected { illegal_move, .. }) => {
+                println!("{}, try again", illegal_move);
+                prompt(&board.read().unwrap());
+            }
+            // the engine never takes moves back
+            Input::Event(GameEvent::UndoRequested { .. }) => {}
+            Input::Event(GameEvent::Undone) => {
+                println!("took back two moves");
+                prompt(&board.read().unwrap());
+            }
+            Input::Line(line) => {
+                let board = board.read().unwrap().clone();
+                let command = line.trim().to_lowercase();
+                let action = match command.as_str() {
+                    "" => None,
+                    "quit" | "exit" => return,
+                    "help" => {
+                        println!("{}", HELP);
+                        None
+                    }
+                    "hint" => {
+                        let mut john = John::new(200_000., 2., 4);
+                        john.debug = false;
+                        println!("hint: {}", john.next(&board));
+                        None
+                    }
+                    "coach" => {
+                        coach = !coach;
+                        println!("coach {}", if coach { "on" } else { "off" });
+                        None
+                    }
+                    "position" => {
+                        println!("{}", board.position());
+                        None
+                    }
+                    "undo" => Some(Action::RequestUndo),
+                    "resign" => Some(Action::Resign),
+                    text => match text.parse::<CellPos>() {
+                        Ok(cp) if board[cp].is_some() => {
+                            println!("{} is taken", cp);
+                            None
+                        }
+                        Ok(cp) => Some(Action::Move(cp)),
+                        Err(_) => {
+                            println!("unknown command, {}", HELP);
+                            None
+                        }
+                    },
+                };
+                match action {
+                    Some(_) if board.turn != options.human => println!("wait for your turn"),
+                    Some(action) => {
+                        actions.send(action).unwrap();
+                        continue;
+                    }
+                    None => {}
+                }
+                if board.turn == options.human {
+                    print!("your move> ");
+                    io::stdout().flush().unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::Write;
+
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::ai::John;
+use crate::arena::{play_game, random_opening};
+
+use super::*;
+
+/// One engine parameter SPSA is allowed to move around.
+#[derive(Clone, Debug)]
+pub struct TunableParam {
+    pub name: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    /// how far both sides are pushed from `value` in the first iteration (c)
+    pub perturbation: f32,
+    /// how far `value` moves for a fully won iteration in the first iteration (a)
+    pub step: f32,
+}
+
+impl TunableParam {
+    pub fn new(name: &'static str, value: f32, min: f32, max: f32, perturbation: f32, step: f32) -> Self {
+        Self { name, value, min, max, perturbation, step }
+    }
+}
+
+/// `John::new(compute, mp, count_iter)`, compute is bounded tightly since more compute always wins.
+pub fn john_params() -> Vec<TunableParam> {
+    vec![
+        TunableParam::new("compute", 100_000., 50_000., 200_000., 20_000., 10_000.),
+        TunableParam::new("mp", 2., 1.1, 4., 0.3, 0.15),
+        TunableParam::new("count_iter", 4., 1., 8., 1., 0.5),
+    ]
+}
+
+pub fn build_john(values: &[f32]) -> Box<dyn Actor> {
+    let mut john = John::new(values[0], values[1], values[2].round() as i32);
+    john.debug = false;
+    Box::new(john)
+}
+
+pub struct Spsa {
+    pub params: Vec<TunableParam>,
+    build: fn(&[f32]) -> Box<dyn Actor>,
+    /// games per iteration, played in pairs with swapped colours on the same opening
+    pub games: usize,
+    pub opening_stones: usize,
+    iteration: usize,
+}
+
+impl Spsa {
+    // usual SPSA gain sequence constants
+    const ALPHA: f32 = 0.602;
+    const GAMMA: f32 = 0.101;
+    const STABILITY: f32 = 10.;
+
+    pub fn new(params: Vec<TunableParam>, build: fn(&[f32]) -> Box<dyn Actor>) -> Self {
+        Self {
+            params,
+            build,
+            games: 4,
+            opening_stones: 3,
+            iteration: 0,
+        }
+    }
+
+    pub fn values(&self) -> Vec<f32> {
+        self.params.iter().map(|p| p.value).collect()
+    }
+
+    /// Runs one iteration, returns the match score of the plus side in [-1, 1].
+    pub fn step<R: Rng + ?Sized>(&mut self, rng: &mut R) -> f32 {
+        self.step_with(rng, Self::play_match)
+    }
+
+    /// `step` with the match between the plus and minus values played by `play_match`.
+    fn step_with<R: Rng + ?Sized>(&mut self, rng: &mut R, play_match: impl FnOnce(&Self, &[f32], &[f32], &mut R) -> f32) -> f32 {
+        let k = self.iteration as f32 + 1.;
+        let a_k = ((1. + Self::STABILITY) / (k + Self::STABILITY)).powf(Self::ALPHA);
+        let c_k = 1. / k.powf(Self::GAMMA);
+
+        let deltas = self.params.iter().map(|_| if rng.gen() { 1. } else { -1. }).collect::<Vec<f32>>();
+        let shifted = |sign: f32| self.params.iter().zip(&deltas).map(|(p, d)| {
+            (p.value + sign * d * p.perturbation * c_k).clamp(p.min, p.max)
+        }).collect::<Vec<_>>();
+        let plus = shifted(1.);
+        let minus = shifted(-1.);
+
+        let result = play_match(self, &plus, &minus, rng);
+
+        for (p, d) in self.params.iter_mut().zip(&deltas) {
+            // gradient estimate is result / (2 c_k d), scaled into the parameter's own units
+            p.value = (p.value + a_k * p.step * result * d / (2. * c_k)).clamp(p.min, p.max);
+        }
+        self.iteration += 1;
+
+        result
+    }
+
+    /// Plays `games` games between engines built from `plus` and `minus`, returns the score of `plus`.
+    fn play_match<R: Rng + ?Sized>(&self, plus: &[f32], minus: &[f32], rng: &mut R) -> f32 {
+        let mut score = 0;
+        let mut opening_seed = 0;
+        for game in 0..self.games {
+            // both colour assignments get the same opening
+            if game % 2 == 0 {
+                opening_seed = rng.gen();
+            }
+            let mut opening_rng = StdRng::seed_from_u64(opening_seed);
+            let mut board = random_opening(self.opening_stones, &mut opening_rng);
+
+            let mut plus_actor = (self.build)(plus);
+            let mut minus_actor = (self.build)(minus);
+            let plus_stone = if game % 2 == 0 { Stone::Black } else { Stone::White };
+            let game_result = match plus_stone {
+                Stone::Black => play_game(&mut board, plus_actor.as_mut(), minus_actor.as_mut()),
+                Stone::White => play_game(&mut board, minus_actor.as_mut(), plus_actor.as_mut()),
+            };
+            match game_result.winner() {
+                Some(stone) if stone == plus_stone => score += 1,
+                Some(_) => score -= 1,
+                None => {}
+            }
+        }
+        score as f32 / self.games as f32
+    }
+
+    /// Runs `iterations` steps, logging the trajectory as csv into `log`.
+    pub fn run<R: Rng + ?Sized>(&mut self, iterations: usize, rng: &mut R, log: &mut impl Write) -> std::io::Result<()> {
+        if self.iteration == 0 {
+            let names = self.params.iter().map(|p| p.name).collect::<Vec<_>>().join(",");
+            writeln!(log, "iteration,score,{names}")?;
+        }
+        for _ in 0..iterations {
+            let result = self.step(rng);
+            let values = self.values().iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+            writeln!(log, "{},{},{}", self.iteration, result, values)?;
+            log.flush()?;
+            println!("SPSA {}: score {} -> {:?}", self.iteration, result, self.values());
+        }
+        Ok(())
+    }
+}
+
+/// Tunes `John` and writes the trajectory into `spsa.csv`.
+pub fn tune_john(iterations: usize) {
+    let mut spsa = Spsa::new(john_params(), build_john);
+    let mut log = File::create("spsa.csv").unwrap();
+    spsa.run(iterations, &mut rand::thread_rng(), &mut log).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_follows_result() {
+        for result in [1., -1.] {
+            let mut spsa = Spsa::new(john_params(), build_john);
+            let before = spsa.values();
+            let mut plus_values = vec![];
+            let score = spsa.step_with(&mut StdRng::seed_from_u64(7), |_, plus, _, _| {
+                plus_values = plus.to_vec();
+                result
+            });
+            assert_eq!(score, result);
+            for ((old, new), plus) in before.iter().zip(spsa.values()).zip(plus_values) {
+                // a won match pulls towards the plus side, a lost one away from it
+                assert_eq!((new - old).signum(), ((plus - old) * result).signum());
+            }
+        }
+    }
+}